@@ -1,61 +1,271 @@
-use crate::client::app_state::{App, UserResponse};
-use serde_json::json;
-// Import App and APP_STATE
-use std::error::Error;
+use crate::client::app_state::App;
+use crate::client::render::local_time;
+use crate::sdk::{ClientError, PresenceStatus, UpdateUserPayload, User};
+
+// --- TUI wrappers around the HTTP API: record the outcome in the status bar ---
+//
+// Calls are queued with `App::request`, so they run after the app is
+// unlocked; the outcome is applied when it arrives.
 
 impl App {
-    pub async fn fetch_user_list(&mut self) -> Result<(), Box<dyn Error>> {
-        let response = self
-            .http_client
-            .get("http://localhost:3000/users")
-            .send()
-            .await?;
-        if response.status().is_success() {
-            let users: Vec<UserResponse> = response.json().await?;
-            self.user_list = users.iter().map(|u| u.username.clone()).collect();
-            self.status = "User list updated.".to_string();
-        } else {
-            self.status = format!("Failed to fetch user list: {}", response.status());
-        }
-        Ok(())
-    }
-
-    pub async fn create_user(&mut self, username: &str) -> Result<(), Box<dyn Error>> {
-        let response = self
-            .http_client
-            .post("http://localhost:3000/users")
-            .json(&json!({"username": username}))
-            .send()
-            .await?;
-        if response.status().is_success() {
-            self.status = format!("User '{}' created successfully.", username);
-            self.fetch_user_list().await?; // Refresh user list after creating user
-        } else {
-            self.status = format!(
-                "Failed to create user '{}': {}",
-                username,
-                response.status()
-            );
+    pub fn fetch_user_list(&mut self) {
+        let client = self.client.clone();
+        self.request(
+            async move { client.list_users().await },
+            |app, users| match users {
+                Ok(users) => {
+                    app.user_list = users.into_iter().map(|u| u.username).collect();
+                    app.status = "User list updated.".to_string();
+                }
+                Err(e) => app.status = format!("Error fetching user list: {}", e),
+            },
+        );
+    }
+
+    pub fn fetch_groups(&mut self) {
+        let client = self.client.clone();
+        self.request(
+            async move { client.list_groups().await },
+            |app, groups| match groups {
+                Ok(groups) => app.sync_groups(groups),
+                Err(e) => app.status = format!("Error fetching groups: {}", e),
+            },
+        );
+    }
+
+    pub fn create_group(&mut self, name: &str, members: &[String]) {
+        let client = self.client.clone();
+        let (requested, members) = (name.to_string(), members.to_vec());
+        let name = name.to_string();
+        let call = async move { client.create_group(&requested, &members).await };
+        self.request(call, move |app, group| match group {
+            Ok(group) => {
+                app.status = format!("Group '{}' created successfully.", group.name);
+                app.fetch_groups();
+            }
+            Err(e @ ClientError::Api { .. }) => {
+                app.status = format!("Failed to create group '{}': {}", name, describe(&e));
+            }
+            Err(e) => app.status = format!("Error creating group: {}", e),
+        });
+    }
+
+    pub fn create_user(&mut self, username: &str, password: &str) {
+        let client = self.client.clone();
+        let (requested, password) = (username.to_string(), password.to_string());
+        let username = username.to_string();
+        let call = async move { client.create_user(&requested, &password).await };
+        self.request(call, move |app, user| match user {
+            Ok(_) => {
+                app.status = format!("User '{}' created successfully.", username);
+                app.fetch_user_list(); // Refresh user list after creating user
+            }
+            Err(e @ ClientError::Api { .. }) => {
+                app.status = format!("Failed to create user '{}': {}", username, describe(&e));
+            }
+            Err(e) => app.status = format!("Error creating user: {}", e),
+        });
+    }
+
+    /// Delete the account called `username`; IDs are opaque, so look it up.
+    pub fn delete_user(&mut self, username: &str) {
+        self.find_user(username, "Error deleting user", |app, user| {
+            let client = app.client.clone();
+            let call = async move { client.delete_user(&user.id).await };
+            app.request(call, move |app, deleted| match deleted {
+                Ok(()) => {
+                    app.status = format!("User '{}' deleted successfully.", user.username);
+                    app.fetch_user_list(); // Refresh user list after deleting user
+                }
+                Err(e @ ClientError::Api { .. }) => {
+                    app.status = format!(
+                        "Failed to delete user '{}': {}",
+                        user.username,
+                        describe(&e)
+                    );
+                }
+                Err(e) => app.status = format!("Error deleting user: {}", e),
+            });
+        });
+    }
+
+    /// Log in as `username` and reconnect so messages carry the new identity.
+    pub fn login(&mut self, username: &str, password: &str) {
+        // The session lives in the client, so log in on a copy and keep it
+        let mut client = self.client.clone();
+        let (requested, password) = (username.to_string(), password.to_string());
+        let username = username.to_string();
+        let call = async move {
+            let user = client.login(&requested, &password).await;
+            (client, user)
+        };
+        self.request(call, move |app, (client, user)| match user {
+            Ok(user) => {
+                app.client = client;
+                app.status = format!("Logged in as '{}'.", user.username);
+                // Membership decides which groups are visible, and so whose
+                // history the new connection loads
+                app.fetch_groups();
+                app.reconnect();
+            }
+            Err(e @ ClientError::Api { .. }) => {
+                app.status = format!("Failed to log in as '{}': {}", username, describe(&e));
+            }
+            Err(e) => app.status = format!("Error logging in: {}", e),
+        });
+    }
+
+    /// Go away, or come back if already away.
+    pub fn toggle_away(&mut self) {
+        let Some(me) = self.client.user().map(|u| u.username.clone()) else {
+            self.status = "Log in with /login to set your presence.".to_string();
+            return;
+        };
+        let away = self.presence.get(&me) != Some(&PresenceStatus::Away);
+        let client = self.client.clone();
+        self.request(
+            async move { client.set_away(away).await },
+            move |app, presence| match presence {
+                Ok(presence) => {
+                    app.presence.insert(presence.username, presence.status);
+                    app.status = if away {
+                        "You are away."
+                    } else {
+                        "You are back."
+                    }
+                    .to_string();
+                }
+                Err(e @ ClientError::Api { .. }) => {
+                    app.status = format!("Failed to set presence: {}", describe(&e));
+                }
+                Err(e) => app.status = format!("Error setting presence: {}", e),
+            },
+        );
+    }
+
+    /// Start or stop sending delivery and read receipts. The setting lives
+    /// in the profile, so every client of the account follows it.
+    pub fn set_receipts(&mut self, on: bool) {
+        if self.client.user().is_none() {
+            self.status = "Log in with /login to change receipts.".to_string();
+            return;
         }
-        Ok(())
-    }
-
-    pub async fn delete_user(&mut self, user_id: usize) -> Result<(), Box<dyn Error>> {
-        let response = self
-            .http_client
-            .delete(&format!("http://localhost:3000/users/{}", user_id))
-            .send()
-            .await?;
-        if response.status().is_success() {
-            self.status = format!("User ID '{}' deleted successfully.", user_id);
-            self.fetch_user_list().await?; // Refresh user list after deleting user
-        } else {
-            self.status = format!(
-                "Failed to delete user ID '{}': {}",
-                user_id,
-                response.status()
-            );
+        let update = UpdateUserPayload {
+            hide_receipts: Some(!on),
+            ..Default::default()
+        };
+        // Updating the profile refreshes the session's copy of it
+        let mut client = self.client.clone();
+        let call = async move {
+            let user = client.update_profile(&update).await;
+            (client, user)
+        };
+        self.request(call, move |app, (client, user)| match user {
+            Ok(_) => {
+                app.client = client;
+                app.status = if on {
+                    "Receipts are on.".to_string()
+                } else {
+                    "Receipts are off; others won't see when you read their messages.".to_string()
+                };
+            }
+            Err(e @ ClientError::Api { .. }) => {
+                app.status = format!("Failed to update receipts: {}", describe(&e));
+            }
+            Err(e) => app.status = format!("Error updating receipts: {}", e),
+        });
+    }
+
+    /// Show `username`'s presence, or our own, in the status bar.
+    pub fn show_presence(&mut self, username: Option<&str>) {
+        let Some(me) = self.client.user().map(|u| u.username.clone()) else {
+            self.status = "Log in with /login to see presence.".to_string();
+            return;
+        };
+        let username = username.unwrap_or(&me).to_string();
+        self.find_user(&username, "Error fetching presence", move |app, user| {
+            let client = app.client.clone();
+            let call = async move { client.presence(&user.id).await };
+            app.request(call, move |app, presence| match presence {
+                Ok(presence) => {
+                    app.status = match (presence.status, presence.last_seen.and_then(local_time)) {
+                        (PresenceStatus::Online, _) => format!("{} is online.", presence.username),
+                        (PresenceStatus::Away, _) => format!("{} is away.", presence.username),
+                        (PresenceStatus::Offline, Some(seen)) => format!(
+                            "{} is offline, last seen {}.",
+                            presence.username,
+                            seen.format("%Y-%m-%d %H:%M")
+                        ),
+                        (PresenceStatus::Offline, None) => {
+                            format!("{} is offline.", presence.username)
+                        }
+                    };
+                    if user.hide_presence && presence.username == me {
+                        app.status += " Others see you as offline.";
+                    }
+                    app.presence.insert(presence.username, presence.status);
+                }
+                Err(e @ ClientError::Api { .. }) => {
+                    app.status = format!("Failed to get presence: {}", describe(&e));
+                }
+                Err(e) => app.status = format!("Error fetching presence: {}", e),
+            });
+        });
+    }
+
+    /// Look up the account called `username` and pass it to `then`; a
+    /// failed lookup goes to the status bar after `context`.
+    fn find_user(
+        &mut self,
+        username: &str,
+        context: &'static str,
+        then: impl FnOnce(&mut App, User) + Send + 'static,
+    ) {
+        let client = self.client.clone();
+        let username = username.to_string();
+        self.request(
+            async move { client.list_users().await },
+            move |app, users| {
+                let users = match users {
+                    Ok(users) => users,
+                    Err(e) => {
+                        app.status = format!("{}: {}", context, e);
+                        return;
+                    }
+                };
+                match users
+                    .into_iter()
+                    .find(|u| u.username.eq_ignore_ascii_case(&username))
+                {
+                    Some(user) => then(app, user),
+                    None => app.status = format!("No user named '{}'.", username),
+                }
+            },
+        );
+    }
+}
+
+/// A failed call in status bar form: the server's own explanation, which
+/// says more than the status code, and the request ID to quote when
+/// reporting a problem.
+fn describe(e: &ClientError) -> String {
+    match e {
+        ClientError::Api {
+            status,
+            message,
+            request_id,
+            ..
+        } => {
+            let mut text = if message.is_empty() {
+                status.to_string()
+            } else {
+                message.clone()
+            };
+            if let Some(id) = request_id {
+                text.push_str(&format!(" (request {})", id));
+            }
+            text
         }
-        Ok(())
+        e => e.to_string(),
     }
 }
@@ -1,44 +1,105 @@
-use futures::stream::SplitSink;
+use crate::client::conversation::Conversation;
+use crate::client::input::LineEditor;
+use crate::client::keymap::Keymap;
+use crate::client::layout::{Focus, Screen};
+use crate::client::theme::Theme;
+use crate::proto::{ReceiptBody, Target, DEFAULT_GROUP};
+use crate::sdk::{PresenceStatus, VeilClient};
+use futures::future::BoxFuture;
 use lazy_static::lazy_static;
-use reqwest::Client;
-use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
 use std::sync::Mutex;
-use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-// Import MaybeTlsStream
-use tokio_tungstenite::tungstenite::Message;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Server the client talks to when none is given on the command line.
+pub const DEFAULT_SERVER_URL: &str = "http://localhost:3000";
 
 // --- App State and Data Structures --
 
+/// A change to make to the app once a request has finished.
+pub type Update = Box<dyn FnOnce(&mut App) + Send>;
+
+/// Network work queued while the app was locked. Whoever holds the lock
+/// takes these, lets go of it and only then awaits them, so a slow request
+/// never holds up the receive loops.
+pub type Request = BoxFuture<'static, Update>;
+
 pub struct App {
-    pub input: String,
-    pub messages: Vec<String>,
+    pub input: LineEditor,
+    pub input_hint: Option<String>, // Completion candidates or command errors, shown under the input
+    pub conversations: Vec<Conversation>, // Sidebar order
+    pub active: usize,              // Index into `conversations`
+    pub history_height: Cell<usize>, // Rows in the chat history viewport, updated on draw
+    pub history_width: Cell<usize>, // Its columns, which decide how messages wrap
     pub status: String,
+    pub latency: Option<Duration>, // Last heartbeat round trip, shown in the status bar
     pub user_list: Vec<String>,
-    pub ws_tx: Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>,
-    pub http_client: Client,
+    pub presence: HashMap<String, PresenceStatus>, // Username -> status, from presence frames
+    pub selected_user: usize, // Index into `user_list` while the user list has focus
+    pub outbox: Vec<(String, ReceiptBody)>, // Receipts waiting to be sent, by recipient
+    pub typing_sent: Option<(Target, Instant)>, // Our last typing frame, to throttle them
+    pub requests: Vec<Request>, // Queued by events, run once the app is unlocked
+    pub client: VeilClient,
+    pub connection_id: u64, // Bumped on every (re)connect so stale receive loops stay quiet
+    pub theme: Theme,
+    pub keymap: Keymap,
+    pub screen: Screen, // Size class of the terminal, updated on resize
+    pub focus: Focus,
+    pub users_popup: bool,
+    // Transfer tasks report progress here rather than lock the app from a
+    // worker thread; the main loop shows the latest in the status bar
+    pub transfer_tx: mpsc::UnboundedSender<String>,
+    pub transfer_rx: mpsc::UnboundedReceiver<String>,
 }
 
 impl Default for App {
     fn default() -> App {
+        let (transfer_tx, transfer_rx) = mpsc::unbounded_channel();
         App {
-            input: String::new(),
-            messages: Vec::new(),
+            input: LineEditor::default(),
+            input_hint: None,
+            conversations: vec![Conversation::new(Target::Group(DEFAULT_GROUP.to_string()))],
+            active: 0,
+            history_height: Cell::new(0),
+            history_width: Cell::new(0),
             status: "Not connected".to_string(),
+            latency: None,
             user_list: Vec::new(),
-            ws_tx: None,
-            http_client: Client::new(),
+            presence: HashMap::new(),
+            selected_user: 0,
+            outbox: Vec::new(),
+            typing_sent: None,
+            requests: Vec::new(),
+            client: VeilClient::new(DEFAULT_SERVER_URL),
+            connection_id: 0,
+            theme: Theme::default(),
+            keymap: Keymap::default(),
+            screen: Screen::default(),
+            focus: Focus::default(),
+            users_popup: false,
+            transfer_tx,
+            transfer_rx,
         }
     }
 }
 
-// --- Data Structures for HTTP Responses ---
-
-#[derive(Deserialize, Debug)]
-pub struct UserResponse {
-    pub id: usize,
-    pub username: String,
+impl App {
+    /// Queue `call` to run once the app is unlocked, and `then` to apply its
+    /// outcome when it arrives. `then` may queue further requests.
+    pub fn request<T: Send + 'static>(
+        &mut self,
+        call: impl Future<Output = T> + Send + 'static,
+        then: impl FnOnce(&mut App, T) + Send + 'static,
+    ) {
+        self.requests.push(Box::pin(async move {
+            let outcome = call.await;
+            Box::new(move |app: &mut App| then(app, outcome)) as Update
+        }));
+    }
 }
 
 // --- Global App State (Mutex for thread safety) ---
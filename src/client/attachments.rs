@@ -0,0 +1,153 @@
+use crate::client::app_state::App;
+use crate::client::conversation::human_size;
+use crate::sdk::{encode_payload, Attachment, ClientError, MessagePayload, Target, VeilClient};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+
+// --- Sending and saving attachments ---
+//
+// Transfers run on their own tasks, file access included, so the UI keeps
+// drawing. They send progress over `App::transfer_tx` instead of locking the
+// app, and the main loop moves the latest into the status bar.
+
+type Progress = UnboundedSender<String>;
+
+impl App {
+    /// Encrypt the file at `path`, upload it and send it to the active
+    /// conversation, with the conversation's timer if one is set.
+    pub fn send_file(&mut self, path: &str) {
+        let conversation = self.active_conversation();
+        let (target, timer) = (conversation.target.clone(), conversation.timer);
+        if self.client.user().is_none() {
+            self.status = "Log in with /login to send files.".to_string();
+            return;
+        }
+        if !self.client.is_connected() {
+            self.status = "Not connected to the server.".to_string();
+            return;
+        }
+        let name = Path::new(path).file_name().map_or_else(
+            || path.to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        self.status = format!("Reading {}...", name);
+        tokio::spawn(upload(
+            self.client.clone(),
+            target,
+            timer,
+            PathBuf::from(path),
+            name,
+            self.transfer_tx.clone(),
+        ));
+    }
+
+    /// Download attachment `number` of the active conversation, decrypt it
+    /// and write it to `path`.
+    pub fn save_attachment(&mut self, number: &str, path: &str) {
+        let attachment = number
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| self.active_conversation().attachments.get(&n))
+            .map(|(_, attachment)| attachment.clone());
+        let Some(attachment) = attachment else {
+            self.input_hint = Some(format!("no attachment [{}] in this conversation", number));
+            return;
+        };
+        self.status = progress_label("Downloading", &attachment.name, 0, attachment.size);
+        tokio::spawn(download(
+            self.client.clone(),
+            attachment,
+            PathBuf::from(path),
+            self.transfer_tx.clone(),
+        ));
+    }
+
+    /// Show the latest progress of background transfers, if any came in.
+    pub fn show_transfer_progress(&mut self) {
+        while let Ok(status) = self.transfer_rx.try_recv() {
+            self.status = status;
+        }
+    }
+}
+
+async fn upload(
+    client: VeilClient,
+    target: Target,
+    timer: Option<u64>,
+    path: PathBuf,
+    name: String,
+    progress: Progress,
+) {
+    let data = match tokio::fs::read(&path).await {
+        Ok(data) if data.is_empty() => {
+            let _ = progress.send(format!("{} is empty.", path.display()));
+            return;
+        }
+        Ok(data) => data,
+        Err(e) => {
+            let _ = progress.send(format!("Could not read {}: {}", path.display(), e));
+            return;
+        }
+    };
+    let (label, updates) = (name.clone(), progress.clone());
+    let sent = client
+        .upload_attachment(&name, &data, move |done, total| {
+            let _ = updates.send(progress_label("Uploading", &label, done, total));
+        })
+        .await;
+    let result = match sent {
+        Ok(attachment) => send_attachment(&client, &target, timer, attachment).await,
+        Err(e) => Err(e),
+    };
+    let _ = progress.send(match result {
+        Ok(()) => format!("Sent {}.", name),
+        Err(e) => format!("Could not send {}: {}", name, e),
+    });
+}
+
+async fn send_attachment(
+    client: &VeilClient,
+    target: &Target,
+    timer: Option<u64>,
+    attachment: Attachment,
+) -> Result<(), ClientError> {
+    let body = encode_payload(&MessagePayload {
+        text: attachment.name.clone(), // What clients without attachments show
+        expires_after_secs: timer,
+        attachment: Some(attachment),
+    });
+    client.send_to(target, &body).await
+}
+
+async fn download(client: VeilClient, attachment: Attachment, path: PathBuf, progress: Progress) {
+    let name = attachment.name.clone();
+    let received = client
+        .download_attachment(&attachment, |done, _| {
+            // The blob's size includes the tag, so count against the file's
+            let _ = progress.send(progress_label("Downloading", &name, done, attachment.size));
+        })
+        .await;
+    let _ = progress.send(match received {
+        Ok(data) => match tokio::fs::write(&path, data).await {
+            Ok(()) => format!("Saved {} to {}.", name, path.display()),
+            Err(e) => format!("Could not write {}: {}", path.display(), e),
+        },
+        Err(e) => format!("Could not download {}: {}", name, e),
+    });
+}
+
+/// "Uploading report.pdf: 1.2 MB of 3.4 MB (35%)".
+pub fn progress_label(verb: &str, name: &str, done: u64, total: u64) -> String {
+    let done = done.min(total);
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    format!(
+        "{} {}: {} of {} ({}%)",
+        verb,
+        name,
+        human_size(done),
+        human_size(total),
+        percent
+    )
+}
@@ -0,0 +1,500 @@
+use crate::client::app_state::App;
+use crate::client::keymap::Action;
+use crate::proto::Target;
+
+// --- Slash command table ---
+//
+// Every command is one `Command` entry: the table drives parsing, argument
+// validation, Tab completion and `/help`, so adding a command only means
+// adding an entry and a handler below.
+
+/// What an argument holds; decides validation and Tab completion.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Username,
+    Group,
+    Command,
+    Word,   // Free-form single word, no completion
+    Secret, // Like `Word`, but the line is kept out of input recall
+    Text,   // Swallows the rest of the line
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    One,
+    Optional,
+    Many, // Zero or more, must come last
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub arity: Arity,
+}
+
+/// Handlers receive arguments that already passed validation. Network calls
+/// are queued with `App::request` rather than awaited.
+pub type Handler = fn(&mut App, &[String]);
+
+pub struct Command {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    pub handler: Handler,
+}
+
+impl Command {
+    /// Usage line, e.g. `/create_group <name> [members...]`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in self.args {
+            usage.push(' ');
+            usage.push_str(&match arg.arity {
+                Arity::One => format!("<{}>", arg.name),
+                Arity::Optional => format!("[{}]", arg.name),
+                Arity::Many => format!("[{}...]", arg.name),
+            });
+        }
+        usage
+    }
+
+    fn parse(&self, words: &[&str]) -> Result<Vec<String>, String> {
+        let mut args = Vec::new();
+        let mut rest = words;
+        for arg in self.args {
+            if arg.kind == ArgKind::Text && !rest.is_empty() {
+                args.push(rest.join(" "));
+                rest = &[];
+                continue;
+            }
+            match (arg.arity, rest.split_first()) {
+                (Arity::Many, _) => {
+                    args.extend(rest.iter().map(|w| w.to_string()));
+                    rest = &[];
+                }
+                (_, Some((word, tail))) => {
+                    args.push(word.to_string());
+                    rest = tail;
+                }
+                (Arity::One, None) => {
+                    return Err(format!("missing <{}>. Usage: {}", arg.name, self.usage()))
+                }
+                (Arity::Optional, None) => {}
+            }
+        }
+        if !rest.is_empty() {
+            return Err(format!("too many arguments. Usage: {}", self.usage()));
+        }
+        Ok(args)
+    }
+}
+
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: &[Arg {
+            name: "command",
+            kind: ArgKind::Command,
+            arity: Arity::Optional,
+        }],
+        help: "List commands, or describe one",
+        handler: help,
+    },
+    Command {
+        name: "keys",
+        args: &[],
+        help: "List key bindings",
+        handler: keys,
+    },
+    Command {
+        name: "login",
+        args: &[
+            Arg {
+                name: "username",
+                kind: ArgKind::Username,
+                arity: Arity::One,
+            },
+            Arg {
+                name: "password",
+                kind: ArgKind::Secret,
+                arity: Arity::One,
+            },
+        ],
+        help: "Log in as an existing user and reconnect",
+        handler: login,
+    },
+    Command {
+        name: "users",
+        args: &[],
+        help: "Refresh the user list",
+        handler: users,
+    },
+    Command {
+        name: "away",
+        args: &[],
+        help: "Mark yourself away, or back if you already are",
+        handler: away,
+    },
+    Command {
+        name: "status",
+        args: &[Arg {
+            name: "username",
+            kind: ArgKind::Username,
+            arity: Arity::Optional,
+        }],
+        help: "Show whether a user, or you, is online",
+        handler: status,
+    },
+    Command {
+        name: "receipts",
+        args: &[Arg {
+            name: "on|off",
+            kind: ArgKind::Word,
+            arity: Arity::One,
+        }],
+        help: "Choose whether others see when you got and read their messages",
+        handler: receipts,
+    },
+    Command {
+        name: "send-file",
+        args: &[Arg {
+            name: "path",
+            kind: ArgKind::Text,
+            arity: Arity::One,
+        }],
+        help: "Send a file here (readable by the server for now)",
+        handler: send_file,
+    },
+    Command {
+        name: "save",
+        args: &[
+            Arg {
+                name: "n",
+                kind: ArgKind::Word,
+                arity: Arity::One,
+            },
+            Arg {
+                name: "path",
+                kind: ArgKind::Text,
+                arity: Arity::One,
+            },
+        ],
+        help: "Download attachment [n] from this conversation to a file",
+        handler: save,
+    },
+    Command {
+        name: "timer",
+        args: &[Arg {
+            name: "duration|off",
+            kind: ArgKind::Word,
+            arity: Arity::One,
+        }],
+        help: "Make your messages here disappear after e.g. 30s, 5m, 2h or 1d",
+        handler: timer,
+    },
+    Command {
+        name: "create_user",
+        args: &[
+            Arg {
+                name: "username",
+                kind: ArgKind::Word,
+                arity: Arity::One,
+            },
+            Arg {
+                name: "password",
+                kind: ArgKind::Secret,
+                arity: Arity::One,
+            },
+        ],
+        help: "Register a new user with a password of 8 or more characters",
+        handler: create_user,
+    },
+    Command {
+        name: "delete_user",
+        args: &[Arg {
+            name: "username",
+            kind: ArgKind::Username,
+            arity: Arity::One,
+        }],
+        help: "Delete your account, or any account as an admin",
+        handler: delete_user,
+    },
+    Command {
+        name: "dm",
+        args: &[Arg {
+            name: "username",
+            kind: ArgKind::Username,
+            arity: Arity::One,
+        }],
+        help: "Open a direct conversation",
+        handler: dm,
+    },
+    Command {
+        name: "open",
+        args: &[Arg {
+            name: "group",
+            kind: ArgKind::Group,
+            arity: Arity::One,
+        }],
+        help: "Switch to a group conversation",
+        handler: open,
+    },
+    Command {
+        name: "create_group",
+        args: &[
+            Arg {
+                name: "name",
+                kind: ArgKind::Word,
+                arity: Arity::One,
+            },
+            Arg {
+                name: "members",
+                kind: ArgKind::Username,
+                arity: Arity::Many,
+            },
+        ],
+        help: "Create a group; without members it is open to everyone",
+        handler: create_group,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+/// Split a `/command args...` line into its command and validated arguments.
+pub fn parse(line: &str) -> Result<(&'static Command, Vec<String>), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let name = words
+        .first()
+        .and_then(|w| w.strip_prefix('/'))
+        .unwrap_or_default();
+    let command = find(name).ok_or_else(|| format!("unknown command /{}. Try /help", name))?;
+    let args = command.parse(&words[1..])?;
+    Ok((command, args))
+}
+
+impl App {
+    /// Run the command in the input bar. Invalid input stays in place with
+    /// the problem shown as a hint, so it can be fixed rather than retyped.
+    pub fn run_command(&mut self) {
+        match parse(self.input.as_str()) {
+            Ok((command, args)) => {
+                if command.args.iter().any(|arg| arg.kind == ArgKind::Secret) {
+                    self.input.discard();
+                } else {
+                    self.input.take();
+                }
+                self.input_hint = None;
+                (command.handler)(self, &args);
+            }
+            Err(e) => self.input_hint = Some(e),
+        }
+    }
+
+    /// Complete the last word of the input from the command table, known
+    /// users or groups; outside commands, words complete to usernames. A
+    /// single match is filled in; several are extended to their common
+    /// prefix and listed as a hint.
+    pub fn complete_input(&mut self) {
+        let text = self.input.as_str();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let partial = if text.ends_with(char::is_whitespace) {
+            ""
+        } else {
+            words.last().copied().unwrap_or_default()
+        };
+        let position = words.len() - usize::from(!partial.is_empty());
+
+        let (prefix, candidates): (&str, Vec<String>) = if !text.starts_with('/') {
+            ("", self.user_list.clone())
+        } else if position == 0 {
+            let names = COMMANDS.iter().map(|c| c.name.to_string()).collect();
+            ("/", names)
+        } else {
+            let kind = words[0]
+                .strip_prefix('/')
+                .and_then(find)
+                .and_then(|c| {
+                    let index = (position - 1).min(c.args.len().saturating_sub(1));
+                    c.args.get(index)
+                })
+                .map(|a| a.kind);
+            ("", self.completion_candidates(kind))
+        };
+
+        let stem = partial.strip_prefix(prefix).unwrap_or(partial);
+        let matches: Vec<&String> = candidates.iter().filter(|c| c.starts_with(stem)).collect();
+        let head = &text[..text.len() - partial.len()];
+        match matches.as_slice() {
+            [] => self.input_hint = Some("no completions".to_string()),
+            [only] => {
+                let completed = format!("{}{}{} ", head, prefix, only);
+                self.input.set_text(completed);
+                self.input_hint = None;
+            }
+            several => {
+                let common = common_prefix(several);
+                let completed = format!("{}{}{}", head, prefix, common);
+                let listed: Vec<&str> = several.iter().map(|s| s.as_str()).collect();
+                self.input.set_text(completed);
+                self.input_hint = Some(listed.join("  "));
+            }
+        }
+    }
+
+    fn completion_candidates(&self, kind: Option<ArgKind>) -> Vec<String> {
+        match kind {
+            Some(ArgKind::Username) => self.user_list.clone(),
+            Some(ArgKind::Group) => self
+                .conversations
+                .iter()
+                .filter_map(|c| match &c.target {
+                    Target::Group(name) => Some(name.clone()),
+                    Target::Direct(_) => None,
+                })
+                .collect(),
+            Some(ArgKind::Command) => COMMANDS.iter().map(|c| c.name.to_string()).collect(),
+            Some(ArgKind::Word | ArgKind::Secret | ArgKind::Text) | None => Vec::new(),
+        }
+    }
+}
+
+fn common_prefix(words: &[&String]) -> String {
+    let first = words[0].as_str();
+    let mut end = first.len();
+    for word in &words[1..] {
+        end = first
+            .char_indices()
+            .zip(word.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(end);
+    }
+    first[..end].to_string()
+}
+
+// --- Handlers ---
+
+fn help(app: &mut App, args: &[String]) {
+    match args.first() {
+        Some(name) => match find(name.trim_start_matches('/')) {
+            Some(command) => app.notice(format!("{}: {}", command.usage(), command.help)),
+            None => app.input_hint = Some(format!("unknown command /{}", name)),
+        },
+        None => {
+            for command in COMMANDS {
+                app.notice(format!("{:<32} {}", command.usage(), command.help));
+            }
+        }
+    }
+}
+
+fn keys(app: &mut App, _args: &[String]) {
+    for action in Action::ALL {
+        let chords: Vec<String> = app
+            .keymap
+            .chords(action)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let bound = if chords.is_empty() {
+            "(unbound)".to_string()
+        } else {
+            chords.join(", ")
+        };
+        app.notice(format!("{:<32} {}", action.name(), bound));
+    }
+}
+
+fn login(app: &mut App, args: &[String]) {
+    app.login(&args[0], &args[1]);
+}
+
+fn users(app: &mut App, _args: &[String]) {
+    app.fetch_user_list();
+}
+
+fn away(app: &mut App, _args: &[String]) {
+    app.toggle_away();
+}
+
+fn status(app: &mut App, args: &[String]) {
+    app.show_presence(args.first().map(String::as_str));
+}
+
+fn receipts(app: &mut App, args: &[String]) {
+    let on = match args[0].as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            app.input_hint = Some("expected on or off. Usage: /receipts <on|off>".to_string());
+            return;
+        }
+    };
+    app.set_receipts(on);
+}
+
+fn send_file(app: &mut App, args: &[String]) {
+    app.send_file(&args[0]);
+}
+
+fn save(app: &mut App, args: &[String]) {
+    app.save_attachment(&args[0], &args[1]);
+}
+
+fn timer(app: &mut App, args: &[String]) {
+    if args[0] == "off" {
+        app.set_timer(None);
+        return;
+    }
+    match parse_duration(&args[0]) {
+        Some(secs) => app.set_timer(Some(secs)),
+        None => app.input_hint = Some(
+            "expected a duration such as 30s, 5m, 2h or 1d, or off. Usage: /timer <duration|off>"
+                .to_string(),
+        ),
+    }
+}
+
+/// Seconds in "45", "45s", "5m", "2h" or "1d"; none for zero or nonsense.
+fn parse_duration(text: &str) -> Option<u64> {
+    let (number, unit) = match text.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&text[..i], c),
+        _ => (text, 's'),
+    };
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        _ => return None,
+    };
+    let secs = number.parse::<u64>().ok()?.checked_mul(scale)?;
+    (secs > 0).then_some(secs)
+}
+
+fn create_user(app: &mut App, args: &[String]) {
+    app.create_user(&args[0], &args[1]);
+}
+
+fn delete_user(app: &mut App, args: &[String]) {
+    app.delete_user(&args[0]);
+}
+
+fn dm(app: &mut App, args: &[String]) {
+    app.open_direct(&args[0]);
+}
+
+fn open(app: &mut App, args: &[String]) {
+    let target = Target::Group(args[0].clone());
+    match app.conversations.iter().position(|c| c.target == target) {
+        Some(index) => app.select_conversation(index),
+        None => app.input_hint = Some(format!("no group named '{}'", args[0])),
+    }
+}
+
+fn create_group(app: &mut App, args: &[String]) {
+    app.create_group(&args[0], &args[1..]);
+}
@@ -0,0 +1,99 @@
+use crate::client::keymap::{Action, KeyChord, Keymap};
+use crate::client::theme::Theme;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// --- Client config file ---
+//
+// Read from `--config PATH`, else `$XDG_CONFIG_HOME/veil/config.toml`
+// (falling back to `~/.config/veil/config.toml`). Everything is optional:
+//
+//     theme = "high-contrast"   # Built-in name, or a theme file path
+//                               # (relative to this file)
+//     [keys]
+//     quit = ["esc", "ctrl+q"]
+//     refresh = "f5"
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub theme: Theme,
+    pub keymap: Keymap,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    theme: Option<String>,
+    #[serde(default)]
+    keys: HashMap<String, Chords>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Chords {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// `$XDG_CONFIG_HOME/veil/config.toml`, or `~/.config/veil/config.toml`.
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("veil").join("config.toml"))
+}
+
+impl Config {
+    /// Load the config. An explicit `path` must exist; the default location
+    /// is optional. `theme` overrides the theme named in the file.
+    pub fn load(path: Option<&Path>, theme: Option<&str>) -> Result<Config, Box<dyn Error>> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| default_config_path().filter(|p| p.exists()));
+        let file = match &path {
+            Some(path) => read_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let theme = match (theme, &file.theme) {
+            (Some(name), _) => Theme::load(name)?,
+            // A theme file named in the config is found relative to it
+            (None, Some(name)) => match Theme::builtin(name) {
+                Some(theme) => theme,
+                None => {
+                    let dir = path.as_deref().and_then(Path::parent);
+                    Theme::from_file(&dir.unwrap_or(Path::new(".")).join(name))?
+                }
+            },
+            (None, None) => Theme::default(),
+        };
+
+        let mut overrides = Vec::new();
+        for (name, chords) in file.keys {
+            let action =
+                Action::from_name(&name).ok_or_else(|| format!("unknown key action '{}'", name))?;
+            let chords = match chords {
+                Chords::One(chord) => vec![chord],
+                Chords::Many(chords) => chords,
+            };
+            let chords = chords
+                .iter()
+                .map(|c| c.parse::<KeyChord>())
+                .collect::<Result<_, _>>()?;
+            overrides.push((action, chords));
+        }
+        let mut keymap = Keymap::default();
+        keymap.rebind(&overrides)?;
+
+        Ok(Config { theme, keymap })
+    }
+}
+
+fn read_file(path: &Path) -> Result<ConfigFile, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read config {}: {}", path.display(), e))?;
+    toml::from_str(&text).map_err(|e| format!("invalid config {}: {}", path.display(), e).into())
+}
@@ -0,0 +1,618 @@
+use crate::client::app_state::App;
+use crate::client::render::entry_heights;
+use crate::proto::{
+    Attachment, ChatMessage, Group, HistoryPage, HistoryQuery, ReceiptBody, ReceiptStatus, Target,
+    Typing,
+};
+use crate::sdk::decode_payload;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Entries kept per conversation; older entries are dropped.
+pub const MAX_HISTORY: usize = 10_000;
+
+/// How long someone counts as typing after their last typing frame.
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One item in a conversation's history.
+pub enum Entry {
+    Message(ChatMessage),
+    Notice(String), // Client-side output such as `/help`
+}
+
+/// How far one of our own messages got, shown as ticks after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Delivery {
+    Sent,      // The server relayed it
+    Delivered, // Someone's client received it
+    Read,      // Someone saw it
+}
+
+impl From<ReceiptStatus> for Delivery {
+    fn from(status: ReceiptStatus) -> Self {
+        match status {
+            ReceiptStatus::Delivered => Delivery::Delivered,
+            ReceiptStatus::Read => Delivery::Read,
+        }
+    }
+}
+
+// --- One entry in the conversation sidebar ---
+
+pub struct Conversation {
+    pub target: Target,
+    pub members: Vec<String>, // Empty for open groups
+    pub history: VecDeque<Entry>,
+    pub unread: usize,
+    pub scroll: usize, // Entries scrolled up from the bottom; 0 follows new messages
+    pub scroll_rows: usize, // Further rows scrolled up, into the entry above those
+    pub unseen_below: usize, // Messages that arrived while scrolled up
+    pub typing: HashMap<String, Instant>, // Username -> their last typing frame
+    pub delivery: HashMap<String, Delivery>, // Id of each of our messages -> how far it got
+    pub expires_at: HashMap<String, u64>, // Message id -> when it disappears, in Unix ms
+    pub timer: Option<u64>, // Seconds our messages here last, set with `/timer`
+    pub attachments: BTreeMap<usize, (String, Attachment)>, // Number for `/save` -> message id and file
+}
+
+impl Conversation {
+    pub fn new(target: Target) -> Self {
+        Conversation {
+            target,
+            members: Vec::new(),
+            history: VecDeque::new(),
+            unread: 0,
+            scroll: 0,
+            scroll_rows: 0,
+            unseen_below: 0,
+            typing: HashMap::new(),
+            delivery: HashMap::new(),
+            expires_at: HashMap::new(),
+            timer: None,
+            attachments: BTreeMap::new(),
+        }
+    }
+
+    /// Append an entry. When scrolled up the view stays where it is and the
+    /// entry is counted for the "new messages below" indicator.
+    pub fn push(&mut self, entry: Entry) {
+        self.history.push_back(entry);
+        self.trim();
+        if self.is_scrolled() {
+            self.scroll = (self.scroll + 1).min(self.history.len());
+            self.unseen_below += 1;
+        }
+    }
+
+    /// Add a received message along with what its payload said, unless it
+    /// is already here. Attachments are numbered in the order they arrive
+    /// and shown by number, for `/save`. Returns whether it was added.
+    fn add_message(&mut self, mut chat: ChatMessage, opened: Opened) -> bool {
+        let number = self.attachments.last_key_value().map_or(1, |(n, _)| n + 1);
+        if let Some(attachment) = &opened.attachment {
+            chat.body = format!(
+                "📎 [{}] {} ({})",
+                number,
+                attachment.name,
+                human_size(attachment.size)
+            );
+        }
+        let id = chat.id.clone();
+        let added = if chat.seq.is_some() {
+            self.insert_by_seq(chat)
+        } else {
+            self.push(Entry::Message(chat));
+            true
+        };
+        if let (true, Some(id)) = (added, id) {
+            if let Some(at) = opened.expires_at {
+                self.expires_at.insert(id.clone(), at);
+            }
+            if let Some(attachment) = opened.attachment {
+                self.attachments.insert(number, (id, attachment));
+            }
+        }
+        added
+    }
+
+    /// Slot a stored group message in by sequence number, which may be
+    /// anywhere when it comes from a history page. Returns false if it is
+    /// already here, e.g. both live and in a page.
+    pub fn insert_by_seq(&mut self, chat: ChatMessage) -> bool {
+        let seq = chat.seq.unwrap_or_default();
+        let mut index = 0;
+        for (i, entry) in self.history.iter().enumerate().rev() {
+            if let Entry::Message(ChatMessage { seq: Some(s), .. }) = entry {
+                if *s == seq {
+                    return false;
+                }
+                if *s < seq {
+                    index = i + 1;
+                    break;
+                }
+            }
+        }
+        if index == self.history.len() {
+            self.push(Entry::Message(chat));
+        } else {
+            self.history.insert(index, Entry::Message(chat));
+            self.trim();
+        }
+        true
+    }
+
+    fn trim(&mut self) {
+        if self.history.len() > MAX_HISTORY {
+            if let Some(Entry::Message(ChatMessage { id: Some(id), .. })) = self.history.pop_front()
+            {
+                self.delivery.remove(&id);
+                self.expires_at.remove(&id);
+                self.attachments.retain(|_, (of, _)| *of != id);
+            }
+        }
+    }
+
+    /// Delete the messages whose timers ran out by `now`.
+    fn expire(&mut self, now: u64) {
+        let expired: Vec<String> = self
+            .expires_at
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        self.history.retain(|entry| match entry {
+            Entry::Message(ChatMessage { id: Some(id), .. }) => !expired.contains(id),
+            _ => true,
+        });
+        for id in &expired {
+            self.expires_at.remove(id);
+            self.delivery.remove(id);
+        }
+        self.attachments.retain(|_, (id, _)| !expired.contains(id));
+        let messages = self
+            .history
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Message(_)))
+            .count();
+        self.unread = self.unread.min(messages);
+        self.scroll = self.scroll.min(self.history.len());
+        if self.scroll == self.history.len() {
+            self.scroll_rows = 0;
+        }
+        self.unseen_below = self.unseen_below.min(self.scroll);
+    }
+
+    /// Sequence number of the newest stored message here, to catch up from.
+    pub fn newest_seq(&self) -> Option<u64> {
+        self.history.iter().rev().find_map(|entry| match entry {
+            Entry::Message(chat) => chat.seq,
+            Entry::Notice(_) => None,
+        })
+    }
+
+    /// Whether the view is scrolled up from the newest messages.
+    pub fn is_scrolled(&self) -> bool {
+        self.scroll > 0 || self.scroll_rows > 0
+    }
+
+    /// Scroll up `rows` rows, stopping once the oldest entry is at the top
+    /// of a `viewport` rows high view. `heights` are the entries' heights in
+    /// rows, oldest first.
+    pub fn scroll_up(&mut self, rows: usize, viewport: usize, heights: &[usize]) {
+        let offset = self.scroll_offset(heights);
+        let max = heights.iter().sum::<usize>().saturating_sub(viewport);
+        self.set_scroll_offset((offset + rows).min(max.max(offset)), heights);
+    }
+
+    pub fn scroll_down(&mut self, rows: usize, heights: &[usize]) {
+        let offset = self.scroll_offset(heights);
+        self.set_scroll_offset(offset.saturating_sub(rows), heights);
+        if !self.is_scrolled() {
+            self.unseen_below = 0;
+        }
+    }
+
+    /// Rows between the bottom of the view and the bottom of the history.
+    fn scroll_offset(&self, heights: &[usize]) -> usize {
+        let below = heights.len().saturating_sub(self.scroll);
+        heights[below..].iter().sum::<usize>() + self.scroll_rows
+    }
+
+    fn set_scroll_offset(&mut self, mut offset: usize, heights: &[usize]) {
+        self.scroll = 0;
+        for height in heights.iter().rev() {
+            if offset < *height {
+                break;
+            }
+            offset -= height;
+            self.scroll += 1;
+        }
+        self.scroll_rows = offset;
+    }
+
+    /// Sidebar label, e.g. `#general (3)`.
+    pub fn label(&self) -> String {
+        if self.unread > 0 {
+            format!("{} ({})", self.target, self.unread)
+        } else {
+            self.target.to_string()
+        }
+    }
+
+    /// Member count for the conversation header.
+    pub fn member_label(&self) -> String {
+        match (&self.target, self.members.len()) {
+            (Target::Direct(_), _) => "2 members".to_string(),
+            (Target::Group(_), 0) => "open group".to_string(),
+            (Target::Group(_), 1) => "1 member".to_string(),
+            (Target::Group(_), n) => format!("{} members", n),
+        }
+    }
+
+    /// "alice is typing…" for the header, if anyone has been lately.
+    pub fn typing_label(&self, now: Instant) -> Option<String> {
+        let mut names: Vec<&str> = self
+            .typing
+            .iter()
+            .filter(|(_, at)| now.saturating_duration_since(**at) < TYPING_TIMEOUT)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        match names.as_slice() {
+            [] => None,
+            [one] => Some(format!("{} is typing…", one)),
+            [one, two] => Some(format!("{} and {} are typing…", one, two)),
+            _ => Some("several people are typing…".to_string()),
+        }
+    }
+
+    /// Ids of the last `unread` messages, grouped by sender, leaving out
+    /// `me`'s own and anonymous ones, which get no receipts.
+    fn unread_by_sender(&self, me: &str) -> HashMap<String, Vec<String>> {
+        let mut unread: HashMap<String, Vec<String>> = HashMap::new();
+        let messages = self.history.iter().rev().filter_map(|entry| match entry {
+            Entry::Message(chat) => Some(chat),
+            Entry::Notice(_) => None,
+        });
+        for chat in messages.take(self.unread) {
+            if let (Some(id), Some(sender)) = (&chat.id, &chat.sender) {
+                if sender != me {
+                    unread.entry(sender.clone()).or_default().push(id.clone());
+                }
+            }
+        }
+        unread
+    }
+
+    /// "⏱ 30s" for the header while our messages here disappear.
+    pub fn timer_label(&self) -> Option<String> {
+        self.timer.map(|secs| format!("⏱ {}", short_duration(secs)))
+    }
+
+    /// Encryption state for the conversation header. Messages travel as
+    /// plaintext until MLS lands, so say so rather than implying otherwise.
+    pub fn encryption_label(&self) -> &'static str {
+        "not encrypted"
+    }
+}
+
+// --- Conversation bookkeeping on the App ---
+
+impl App {
+    pub fn active_conversation(&self) -> &Conversation {
+        &self.conversations[self.active]
+    }
+
+    /// Find the conversation for `target`, adding it to the sidebar if new.
+    pub fn conversation_mut(&mut self, target: &Target) -> &mut Conversation {
+        let index = match self.conversations.iter().position(|c| &c.target == target) {
+            Some(index) => index,
+            None => {
+                self.conversations.push(Conversation::new(target.clone()));
+                self.conversations.len() - 1
+            }
+        };
+        &mut self.conversations[index]
+    }
+
+    /// Switch to conversation `index`, marking what arrived there read.
+    pub fn select_conversation(&mut self, index: usize) {
+        let Some(conversation) = self.conversations.get_mut(index) else {
+            return;
+        };
+        let unread = match self.client.user() {
+            Some(me) if !me.hide_receipts => conversation.unread_by_sender(&me.username),
+            _ => HashMap::new(),
+        };
+        conversation.unread = 0;
+        self.active = index;
+        for (sender, ids) in unread {
+            self.queue_receipt(&sender, ReceiptStatus::Read, ids);
+        }
+    }
+
+    pub fn next_conversation(&mut self) {
+        self.select_conversation((self.active + 1) % self.conversations.len());
+    }
+
+    pub fn previous_conversation(&mut self) {
+        let count = self.conversations.len();
+        self.select_conversation((self.active + count - 1) % count);
+    }
+
+    /// Scroll the active conversation by whole pages (PageUp/PageDown).
+    pub fn scroll_pages(&mut self, pages: isize) {
+        let height = self.history_height.get().max(1);
+        self.scroll_lines(pages * height as isize);
+    }
+
+    /// Scroll the active conversation by rows as drawn; positive values
+    /// move back in time.
+    pub fn scroll_lines(&mut self, lines: isize) {
+        let (width, height) = (self.history_width.get(), self.history_height.get());
+        let heights = entry_heights(self.active_conversation(), width, &self.theme);
+        let conversation = &mut self.conversations[self.active];
+        if lines > 0 {
+            conversation.scroll_up(lines as usize, height, &heights);
+        } else {
+            conversation.scroll_down(lines.unsigned_abs(), &heights);
+        }
+    }
+
+    /// Open (or switch to) the direct conversation with `username`. Names
+    /// are matched ignoring case and filed under the registered spelling,
+    /// as the server does, so replies land in the same conversation.
+    pub fn open_direct(&mut self, username: &str) {
+        let existing = self.conversations.iter().find_map(|c| match &c.target {
+            Target::Direct(name) if name.eq_ignore_ascii_case(username) => Some(name),
+            _ => None,
+        });
+        let registered = || {
+            self.user_list
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(username))
+        };
+        let username = existing
+            .or_else(registered)
+            .map_or(username, String::as_str);
+        let target = Target::Direct(username.to_string());
+        self.conversation_mut(&target);
+        if let Some(index) = self.conversations.iter().position(|c| c.target == target) {
+            self.select_conversation(index);
+        }
+    }
+
+    /// File an incoming message under its conversation, counting it as
+    /// unread unless that conversation is on screen. Our own messages start
+    /// out sent; others' get a receipt queued, read if they are on screen.
+    /// Disappearing messages whose time is already up are dropped.
+    pub fn receive_message(&mut self, mut chat: ChatMessage) {
+        let Some(opened) = open_payload(&mut chat, unix_millis()) else {
+            return;
+        };
+        let me = self.client.user().cloned();
+        let target = chat.conversation_for(me.as_ref().map(|u| u.username.as_str()));
+        let is_active = self.active_conversation().target == target;
+        let conversation = self.conversation_mut(&target);
+        let mut receipt = None;
+        if let (Some(id), Some(sender)) = (&chat.id, &chat.sender) {
+            conversation.typing.remove(sender);
+            match &me {
+                Some(me) if me.username == *sender => {
+                    conversation.delivery.insert(id.clone(), Delivery::Sent);
+                }
+                Some(me) if !me.hide_receipts => receipt = Some((sender.clone(), id.clone())),
+                _ => {}
+            }
+        }
+        if !conversation.add_message(chat, opened) {
+            return; // Already came with a history page
+        }
+        if !is_active {
+            conversation.unread += 1;
+        }
+        if let Some((sender, id)) = receipt {
+            let status = if is_active {
+                ReceiptStatus::Read
+            } else {
+                ReceiptStatus::Delivered
+            };
+            self.queue_receipt(&sender, status, vec![id]);
+        }
+    }
+
+    /// What to ask for to load each group's history: the newest page for
+    /// groups we have nothing from yet, otherwise whatever came since.
+    pub fn history_queries(&self) -> Vec<(String, HistoryQuery)> {
+        self.conversations
+            .iter()
+            .filter_map(|c| match &c.target {
+                Target::Group(group) => Some((group.clone(), c.newest_seq())),
+                Target::Direct(_) => None,
+            })
+            .map(|(group, newest)| {
+                let query = HistoryQuery {
+                    after: newest,
+                    ..Default::default()
+                };
+                (group, query)
+            })
+            .collect()
+    }
+
+    /// Merge a page of group history. Stored messages don't count as unread
+    /// or get receipts. Returns the next page to ask for while catching up.
+    pub fn receive_history(&mut self, page: HistoryPage) -> Option<HistoryQuery> {
+        let me = self.client.user().map(|u| u.username.clone());
+        let now = unix_millis();
+        let conversation = self.conversation_mut(&Target::Group(page.group));
+        let newest = conversation.newest_seq();
+        let first = page.messages.first().and_then(|chat| chat.seq);
+        let last = page.messages.last().and_then(|chat| chat.seq);
+        for mut chat in page.messages {
+            let Some(opened) = open_payload(&mut chat, now) else {
+                continue;
+            };
+            if chat.sender.is_some() && chat.sender == me {
+                if let Some(id) = &chat.id {
+                    conversation
+                        .delivery
+                        .entry(id.clone())
+                        .or_insert(Delivery::Sent);
+                }
+            }
+            conversation.add_message(chat, opened);
+        }
+        // Pages read forward from what we had are followed to the end
+        let catching_up = matches!((newest, first), (Some(newest), Some(first)) if first > newest);
+        (page.more && catching_up).then(|| HistoryQuery {
+            after: last,
+            ..Default::default()
+        })
+    }
+
+    /// Delete disappearing messages whose time is up, everywhere.
+    pub fn expire_messages(&mut self, now: u64) {
+        for conversation in &mut self.conversations {
+            conversation.expire(now);
+        }
+    }
+
+    /// Set how long our messages in the active conversation last; `None`
+    /// sends them normally again.
+    pub fn set_timer(&mut self, secs: Option<u64>) {
+        self.conversations[self.active].timer = secs;
+        let notice = match secs {
+            Some(secs) => format!(
+                "Messages you send here now disappear after {}.",
+                short_duration(secs)
+            ),
+            None => "Messages you send here no longer disappear.".to_string(),
+        };
+        self.notice(notice);
+    }
+
+    /// Note that someone is typing; the header shows it for a few seconds.
+    pub fn receive_typing(&mut self, typing: Typing) {
+        let me = self.client.user().map(|u| u.username.clone());
+        let Some(sender) = typing.sender.clone() else {
+            return;
+        };
+        let target = typing.conversation_for(me.as_deref());
+        self.conversation_mut(&target)
+            .typing
+            .insert(sender, Instant::now());
+    }
+
+    /// Move the ticks on our messages forward; receipts never move them
+    /// back, e.g. when a delivery receipt arrives after a read one.
+    pub fn receive_receipt(&mut self, body: ReceiptBody) {
+        let status = Delivery::from(body.status);
+        for id in &body.message_ids {
+            let known = self
+                .conversations
+                .iter_mut()
+                .find_map(|c| c.delivery.get_mut(id));
+            if let Some(delivery) = known {
+                *delivery = (*delivery).max(status);
+            }
+        }
+    }
+
+    /// Queue a receipt to send once the app is no longer borrowed.
+    fn queue_receipt(&mut self, to: &str, status: ReceiptStatus, message_ids: Vec<String>) {
+        match self
+            .outbox
+            .iter_mut()
+            .find(|(name, body)| name == to && body.status == status)
+        {
+            Some((_, body)) => body.message_ids.extend(message_ids),
+            None => self.outbox.push((
+                to.to_string(),
+                ReceiptBody {
+                    status,
+                    message_ids,
+                },
+            )),
+        }
+    }
+
+    /// Show client-side output such as `/help` in the active conversation.
+    pub fn notice(&mut self, line: String) {
+        self.conversations[self.active].push(Entry::Notice(line));
+    }
+
+    /// Add groups this user can see to the sidebar and refresh member lists.
+    pub fn sync_groups(&mut self, groups: Vec<Group>) {
+        let me = self.client.user().map(|u| u.username.clone());
+        for group in groups {
+            let visible = group.members.is_empty()
+                || me
+                    .as_ref()
+                    .is_some_and(|me| group.members.iter().any(|m| m.eq_ignore_ascii_case(me)));
+            if visible {
+                self.conversation_mut(&Target::Group(group.name)).members = group.members;
+            }
+        }
+    }
+}
+
+/// What a message's payload said besides its text.
+struct Opened {
+    expires_at: Option<u64>, // Unix ms
+    attachment: Option<Attachment>,
+}
+
+/// Replace a message's body with the text of its payload and return the
+/// rest, or `None` if the message's time is already up.
+fn open_payload(chat: &mut ChatMessage, now: u64) -> Option<Opened> {
+    let payload = decode_payload(&chat.body);
+    chat.body = payload.text;
+    let expires_at = payload.expires_after_secs.map(|secs| {
+        chat.sent_at
+            .unwrap_or(now)
+            .saturating_add(secs.saturating_mul(1000))
+    });
+    if expires_at.is_some_and(|at| at <= now) {
+        return None;
+    }
+    Some(Opened {
+        expires_at,
+        attachment: payload.attachment,
+    })
+}
+
+/// Milliseconds since the Unix epoch, the clock message timers run on.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// "45s", "5m", "2h" or "1d", in the largest unit that divides `secs`.
+pub fn short_duration(secs: u64) -> String {
+    match secs {
+        0 => "0s".to_string(),
+        s if s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// "512 B", "12.0 KB" or "3.4 MB".
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,95 @@
+use crate::client::app_state::App;
+use crate::client::keymap::Action;
+use crate::client::layout::Focus;
+use crossterm::event::{Event, KeyEvent, KeyEventKind, MouseEventKind};
+
+// --- Terminal event handling ---
+//
+// Kept apart from the terminal itself so the same code path can be driven
+// by tests with synthetic events.
+
+/// What the event loop should do next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+impl App {
+    /// Apply one terminal event. On resize only the layout state changes;
+    /// resizing the terminal itself is up to the caller. Network calls the
+    /// event needs are left in `requests` for the caller to run.
+    pub fn handle_event(&mut self, event: Event) -> Flow {
+        let flow = match event {
+            Event::Key(key) => self.handle_key(key),
+            Event::Paste(text) => {
+                self.focus = Focus::Input;
+                self.input.insert_str(&text);
+                Flow::Continue
+            }
+            Event::Resize(width, height) => {
+                self.resize(width, height);
+                Flow::Continue
+            }
+            Event::Mouse(mouse) => {
+                match mouse.kind {
+                    MouseEventKind::ScrollUp => self.scroll_lines(3),
+                    MouseEventKind::ScrollDown => self.scroll_lines(-3),
+                    _ => {}
+                }
+                Flow::Continue
+            }
+            Event::FocusGained | Event::FocusLost => Flow::Continue,
+        };
+        // E.g. read receipts for a conversation that was just opened
+        self.flush_receipts();
+        flow
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Flow {
+        if key.kind == KeyEventKind::Release {
+            return Flow::Continue;
+        }
+        match self.keymap.action(&key) {
+            // Esc closes the popup before it quits
+            Some(Action::Quit) if self.users_popup => self.toggle_users(),
+            Some(Action::Quit) => return Flow::Quit,
+            Some(Action::Complete) if self.input.is_empty() => self.next_conversation(),
+            Some(Action::Complete) => self.complete_input(),
+            Some(Action::NextConversation) => self.next_conversation(),
+            Some(Action::PreviousConversation) => self.previous_conversation(),
+            Some(Action::ScrollUp) => self.scroll_pages(1),
+            Some(Action::ScrollDown) => self.scroll_pages(-1),
+            Some(Action::Refresh) => {
+                self.fetch_user_list();
+                self.fetch_groups();
+            }
+            Some(Action::FocusNext) => self.focus_next(),
+            Some(Action::FocusPrevious) => self.focus_previous(),
+            Some(Action::ToggleUsers) => self.toggle_users(),
+            Some(Action::Submit) if self.focus != Focus::Input => self.activate_focused(),
+            Some(Action::Submit) if self.input.is_empty() => {}
+            Some(Action::Submit) => {
+                if self.input.as_str().starts_with('/') {
+                    self.run_command();
+                } else if self.client.is_connected() {
+                    let input = self.input.take();
+                    self.send_message(input);
+                } else {
+                    self.status = "Not connected to WebSocket. Cannot send message.".to_string();
+                }
+            }
+            None if self.focus == Focus::Input => {
+                // Everything else is line editing
+                if self.input.handle_key(key) {
+                    self.input_hint = None;
+                    if !self.input.is_empty() && !self.input.as_str().starts_with('/') {
+                        self.send_typing();
+                    }
+                }
+            }
+            None => self.pane_key(key),
+        }
+        Flow::Continue
+    }
+}
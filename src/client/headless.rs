@@ -0,0 +1,117 @@
+// Non-interactive commands for scripts and cron jobs. These share the SDK
+// with the TUI but never touch the terminal.
+
+use crate::sdk::{decode_payload, Event, Target, VeilClient};
+use futures::StreamExt;
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// How long `send` waits to hear whether its message went through.
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Post a single message to `group` and exit, logged in as `sender` if given,
+/// with the password from `VEIL_PASSWORD`. Waits for the server to echo the
+/// message back, and fails if it refuses it instead.
+pub async fn send(
+    server_url: &str,
+    group: &str,
+    sender: Option<String>,
+    text: String,
+) -> Result<(), Box<dyn Error>> {
+    let mut client = VeilClient::new(server_url);
+    let groups = client.list_groups().await?;
+    if !groups.iter().any(|g| g.name == group) {
+        return Err(format!("Unknown group '{}'", group).into());
+    }
+    if let Some(username) = &sender {
+        log_in(&mut client, username).await?;
+    }
+
+    let mut events = client.connect().await?;
+    client.send(group, &text).await?;
+    let target = Target::Group(group.to_string());
+    let me = client.user().map(|u| u.username.clone());
+    let outcome = tokio::time::timeout(SEND_TIMEOUT, async {
+        while let Some(event) = events.next().await {
+            match event? {
+                Event::Message(chat)
+                    if chat.target == target && chat.sender == me && chat.body == text =>
+                {
+                    return Ok(())
+                }
+                Event::Error(error) => {
+                    return Err(format!("Server refused the message: {}", error.message).into())
+                }
+                _ => {}
+            }
+        }
+        Err::<(), Box<dyn Error>>("Connection closed before the message was delivered".into())
+    })
+    .await
+    .unwrap_or_else(|_| Err("Server did not confirm the message in time".into()));
+    client.disconnect().await?;
+    outcome
+}
+
+/// Print incoming messages until the server closes the connection, logged in
+/// as `reader` if given so private groups are visible too.
+///
+/// With `json` set every message is written as one JSON object per line,
+/// body untouched.
+pub async fn tail(
+    server_url: &str,
+    group: Option<&str>,
+    reader: Option<String>,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut client = VeilClient::new(server_url);
+    if let Some(username) = reader {
+        log_in(&mut client, &username).await?;
+    }
+    let mut events = client.connect().await?;
+    let mut stdout = io::stdout();
+
+    while let Some(event) = events.next().await {
+        let Event::Message(mut chat) = event? else {
+            continue;
+        };
+        if group.is_some_and(|g| chat.target != Target::Group(g.to_string())) {
+            continue;
+        }
+        if json {
+            writeln!(stdout, "{}", serde_json::to_string(&chat)?)?;
+        } else {
+            // Show what was said; the JSON above keeps the body as sent
+            chat.body = decode_payload(&chat.body).text;
+            writeln!(stdout, "{}", chat.display_line())?;
+        }
+        // Flush per line so pipes see messages as they arrive
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Log in as `username` with the password from `VEIL_PASSWORD`.
+async fn log_in(client: &mut VeilClient, username: &str) -> Result<(), Box<dyn Error>> {
+    let password = std::env::var("VEIL_PASSWORD")
+        .map_err(|_| format!("Set VEIL_PASSWORD to log in as '{}'", username))?;
+    client.login(username, &password).await?;
+    Ok(())
+}
+
+/// Print every user as `id<TAB>username`.
+pub async fn users(server_url: &str) -> Result<(), Box<dyn Error>> {
+    for user in VeilClient::new(server_url).list_users().await? {
+        println!("{}\t{}", user.id, user.username);
+    }
+    Ok(())
+}
+
+/// Print every group as `name<TAB>comma-separated members`.
+pub async fn groups(server_url: &str) -> Result<(), Box<dyn Error>> {
+    for group in VeilClient::new(server_url).list_groups().await? {
+        println!("{}\t{}", group.name, group.members.join(","));
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Submitted lines remembered for Up/Down recall.
+pub const MAX_INPUT_HISTORY: usize = 100;
+
+// --- Line editor behind the input bar ---
+//
+// `cursor` is a byte offset into `text` that always sits on a grapheme
+// boundary, so cursor movement and deletion never split a character.
+
+#[derive(Default)]
+pub struct LineEditor {
+    text: String,
+    cursor: usize,
+    history: VecDeque<String>,    // Oldest first
+    history_index: Option<usize>, // Position while recalling, None when editing
+    draft: String,                // What was typed before recall started
+}
+
+impl LineEditor {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Take the text for submission, remembering it for recall.
+    pub fn take(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        self.history_index = None;
+        if !text.is_empty() && self.history.back() != Some(&text) {
+            self.history.push_back(text.clone());
+            if self.history.len() > MAX_INPUT_HISTORY {
+                self.history.pop_front();
+            }
+        }
+        text
+    }
+
+    /// Clear the text without remembering it, for lines holding a password.
+    pub fn discard(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        self.history_index = None;
+    }
+
+    /// Apply an editing key. Returns false for keys the editor doesn't use,
+    /// so the caller can give them another meaning.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.home(),
+            KeyCode::Char('e') if ctrl => self.end(),
+            KeyCode::Char('b') if ctrl => self.left(),
+            KeyCode::Char('f') if ctrl => self.right(),
+            KeyCode::Char('k') if ctrl => self.kill_to_end(),
+            KeyCode::Char('w') if ctrl => self.delete_word_left(),
+            KeyCode::Char('b') if alt => self.word_left(),
+            KeyCode::Char('f') if alt => self.word_right(),
+            KeyCode::Char('d') if alt => self.delete_word_right(),
+            KeyCode::Char(c) if !ctrl && !alt => self.insert_str(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Backspace if ctrl || alt => self.delete_word_left(),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete if ctrl || alt => self.delete_word_right(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left if ctrl || alt => self.word_left(),
+            KeyCode::Right if ctrl || alt => self.word_right(),
+            KeyCode::Left => self.left(),
+            KeyCode::Right => self.right(),
+            KeyCode::Home => self.home(),
+            KeyCode::End => self.end(),
+            KeyCode::Up => self.up(),
+            KeyCode::Down => self.down(),
+            // Shift-Enter needs keyboard enhancement support; Alt-Enter works everywhere
+            KeyCode::Enter
+                if key
+                    .modifiers
+                    .intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) =>
+            {
+                self.insert_str("\n")
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Insert text at the cursor, e.g. a typed character or a paste.
+    pub fn insert_str(&mut self, s: &str) {
+        let s = s.replace("\r\n", "\n").replace('\r', "\n");
+        self.text.insert_str(self.cursor, &s);
+        self.cursor += s.len();
+    }
+
+    /// Cursor position in terminal cells as (column, row), accounting for
+    /// wide characters.
+    pub fn cursor_position(&self) -> (u16, u16) {
+        let before = &self.text[..self.cursor];
+        let row = before.matches('\n').count();
+        let col = before[self.line_start()..].width();
+        (col as u16, row as u16)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.matches('\n').count() + 1
+    }
+
+    // --- Movement ---
+
+    fn left(&mut self) {
+        self.cursor = self.prev_boundary(self.cursor);
+    }
+
+    fn right(&mut self) {
+        self.cursor = self.next_boundary(self.cursor);
+    }
+
+    fn home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    fn end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    fn word_left(&mut self) {
+        self.cursor = self.word_start_before(self.cursor);
+    }
+
+    fn word_right(&mut self) {
+        self.cursor = self.word_end_after(self.cursor);
+    }
+
+    /// Move up a line, or recall older input when already on the first line.
+    fn up(&mut self) {
+        let start = self.line_start();
+        if start == 0 {
+            self.recall_older();
+        } else {
+            let col = self.text[start..self.cursor].width();
+            let prev_start = self.text[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+            self.cursor = self.offset_at_width(prev_start, start - 1, col);
+        }
+    }
+
+    /// Move down a line, or recall newer input when already on the last line.
+    fn down(&mut self) {
+        let end = self.line_end();
+        if end == self.text.len() {
+            self.recall_newer();
+        } else {
+            let col = self.text[self.line_start()..self.cursor].width();
+            let next_end = self.text[end + 1..]
+                .find('\n')
+                .map_or(self.text.len(), |i| end + 1 + i);
+            self.cursor = self.offset_at_width(end + 1, next_end, col);
+        }
+    }
+
+    // --- Deletion ---
+
+    fn backspace(&mut self) {
+        let start = self.prev_boundary(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    fn delete(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    fn delete_word_left(&mut self) {
+        let start = self.word_start_before(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    fn delete_word_right(&mut self) {
+        let end = self.word_end_after(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Ctrl-K: delete to the end of the line, or join with the next line
+    /// when already there.
+    fn kill_to_end(&mut self) {
+        let end = self.line_end();
+        if end == self.cursor {
+            self.delete();
+        } else {
+            self.text.replace_range(self.cursor..end, "");
+        }
+    }
+
+    // --- History recall ---
+
+    fn recall_older(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.set_text(self.history[index].clone());
+    }
+
+    fn recall_newer(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.set_text(self.history[index + 1].clone());
+        } else {
+            self.history_index = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.set_text(draft);
+        }
+    }
+
+    /// Replace the text and move the cursor to the end.
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+        self.cursor = self.text.len();
+    }
+
+    // --- Boundaries ---
+
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    fn prev_boundary(&self, offset: usize) -> usize {
+        self.text[..offset]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self, offset: usize) -> usize {
+        self.text[offset..]
+            .graphemes(true)
+            .next()
+            .map_or(offset, |g| offset + g.len())
+    }
+
+    /// Start of the word before `offset`, skipping any whitespace first.
+    fn word_start_before(&self, offset: usize) -> usize {
+        let mut start = offset;
+        let mut seen_word = false;
+        for (i, g) in self.text[..offset].grapheme_indices(true).rev() {
+            let is_space = g.chars().all(char::is_whitespace);
+            if is_space && seen_word {
+                break;
+            }
+            seen_word |= !is_space;
+            start = i;
+        }
+        start
+    }
+
+    /// End of the word after `offset`, skipping any whitespace first.
+    fn word_end_after(&self, offset: usize) -> usize {
+        let mut end = offset;
+        let mut seen_word = false;
+        for (i, g) in self.text[offset..].grapheme_indices(true) {
+            let is_space = g.chars().all(char::is_whitespace);
+            if is_space && seen_word {
+                break;
+            }
+            seen_word |= !is_space;
+            end = offset + i + g.len();
+        }
+        end
+    }
+
+    /// Offset within `start..end` whose display column is closest to `col`
+    /// without passing it.
+    fn offset_at_width(&self, start: usize, end: usize, col: usize) -> usize {
+        let mut width = 0;
+        for (i, g) in self.text[start..end].grapheme_indices(true) {
+            width += g.width();
+            if width > col {
+                return start + i;
+            }
+        }
+        end
+    }
+}
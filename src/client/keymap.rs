@@ -0,0 +1,249 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// --- Key bindings ---
+//
+// Application-level keys are looked up here before the line editor sees
+// them, so any chord can be rebound from the config file. Editing keys
+// (cursor movement, Ctrl-W, ...) stay with `LineEditor`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Submit,   // Send the input, or run it as a command
+    Complete, // Tab completion; switches conversation on empty input
+    NextConversation,
+    PreviousConversation,
+    ScrollUp, // One page
+    ScrollDown,
+    Refresh, // Reload users and groups
+    FocusNext,
+    FocusPrevious,
+    ToggleUsers, // User list popup
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::Quit,
+        Action::Submit,
+        Action::Complete,
+        Action::NextConversation,
+        Action::PreviousConversation,
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::Refresh,
+        Action::FocusNext,
+        Action::FocusPrevious,
+        Action::ToggleUsers,
+    ];
+
+    /// Name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Submit => "submit",
+            Action::Complete => "complete",
+            Action::NextConversation => "next_conversation",
+            Action::PreviousConversation => "previous_conversation",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollDown => "scroll_down",
+            Action::Refresh => "refresh",
+            Action::FocusNext => "focus_next",
+            Action::FocusPrevious => "focus_previous",
+            Action::ToggleUsers => "toggle_users",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// A key plus modifiers, written like `ctrl+n`, `shift+tab` or `pageup`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        // Shift is already part of a character ('N' vs 'n') and of BackTab,
+        // and terminals disagree on whether to report it as well
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        KeyChord { code, modifiers }
+    }
+}
+
+impl From<&KeyEvent> for KeyChord {
+    fn from(key: &KeyEvent) -> KeyChord {
+        KeyChord::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<KeyChord, String> {
+        let invalid = || format!("invalid key '{}'", text);
+        // Split on '+' but keep a trailing '+' as the key itself, as in "ctrl++"
+        let (mods, key) = match text.strip_suffix("++") {
+            Some(mods) => (mods, "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for name in mods.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(invalid()),
+            };
+        }
+        let shift = modifiers.contains(KeyModifiers::SHIFT);
+        let code = match key.to_ascii_lowercase().as_str() {
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" if shift => KeyCode::BackTab,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            lower => {
+                if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    KeyCode::F(n)
+                } else {
+                    let mut chars = key.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) if shift => KeyCode::Char(c.to_ascii_uppercase()),
+                        (Some(c), None) => KeyCode::Char(c),
+                        _ => return Err(invalid()),
+                    }
+                }
+            }
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::BackTab => write!(f, "shift+tab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Insert => write!(f, "insert"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: HashMap<KeyChord, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let defaults = [
+            ("esc", Action::Quit),
+            ("enter", Action::Submit),
+            ("tab", Action::Complete),
+            ("shift+tab", Action::PreviousConversation),
+            ("ctrl+n", Action::NextConversation),
+            ("ctrl+p", Action::PreviousConversation),
+            ("pageup", Action::ScrollUp),
+            ("pagedown", Action::ScrollDown),
+            ("ctrl+u", Action::Refresh),
+            ("f6", Action::FocusNext),
+            ("shift+f6", Action::FocusPrevious),
+            ("f2", Action::ToggleUsers),
+        ];
+        let bindings = defaults
+            .into_iter()
+            .map(|(chord, action)| (chord.parse().expect("valid default chord"), action))
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings.get(&KeyChord::from(key)).copied()
+    }
+
+    /// Chords bound to `action`, sorted for display.
+    pub fn chords(&self, action: Action) -> Vec<KeyChord> {
+        let mut chords: Vec<KeyChord> = self
+            .bindings
+            .iter()
+            .filter(|(_, a)| **a == action)
+            .map(|(chord, _)| *chord)
+            .collect();
+        chords.sort_by_key(|c| c.to_string());
+        chords
+    }
+
+    /// Replace the chords of each listed action. An overridden chord that a
+    /// default binding also used is taken over; binding one chord to two
+    /// listed actions is an error.
+    pub fn rebind(&mut self, overrides: &[(Action, Vec<KeyChord>)]) -> Result<(), String> {
+        let rebound: Vec<Action> = overrides.iter().map(|(action, _)| *action).collect();
+        self.bindings.retain(|_, action| !rebound.contains(action));
+        let mut taken = HashMap::new();
+        for (action, chords) in overrides {
+            for chord in chords {
+                if let Some(other) = taken.insert(*chord, *action) {
+                    if other != *action {
+                        return Err(format!(
+                            "{} is bound to both {} and {}",
+                            chord,
+                            other.name(),
+                            action.name()
+                        ));
+                    }
+                }
+                self.bindings.insert(*chord, *action);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::client::app_state::App;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// --- Screen size classes and pane focus ---
+//
+// The layout adapts to the terminal: below `WIDE_MIN_WIDTH` columns the
+// conversation sidebar collapses, and below `TALL_MIN_HEIGHT` rows the
+// status bar and header shrink to one line each and the user list is only
+// available as a popup.
+
+pub const WIDE_MIN_WIDTH: u16 = 80;
+pub const TALL_MIN_HEIGHT: u16 = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Screen {
+    pub wide: bool, // Room for the sidebar
+    pub tall: bool, // Room for bordered bars and the user list pane
+}
+
+impl Screen {
+    pub fn for_size(width: u16, height: u16) -> Screen {
+        Screen {
+            wide: width >= WIDE_MIN_WIDTH,
+            tall: height >= TALL_MIN_HEIGHT,
+        }
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen {
+            wide: true,
+            tall: true,
+        }
+    }
+}
+
+/// The pane that receives keys without an application-wide binding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    Input,
+    History,
+    Sidebar,
+    Users,
+}
+
+impl App {
+    /// Whether `pane` is on screen in the current layout.
+    pub fn pane_visible(&self, pane: Focus) -> bool {
+        match pane {
+            Focus::Input | Focus::History => true,
+            Focus::Sidebar => self.screen.wide,
+            Focus::Users => self.screen.tall || self.users_popup,
+        }
+    }
+
+    pub fn focus_next(&mut self) {
+        self.cycle_focus(1);
+    }
+
+    pub fn focus_previous(&mut self) {
+        self.cycle_focus(-1);
+    }
+
+    fn cycle_focus(&mut self, step: isize) {
+        const ORDER: [Focus; 4] = [Focus::Input, Focus::History, Focus::Sidebar, Focus::Users];
+        let mut index = ORDER.iter().position(|f| *f == self.focus).unwrap_or(0);
+        // Input and History are always visible, so this terminates
+        loop {
+            index = (index as isize + step).rem_euclid(ORDER.len() as isize) as usize;
+            if self.pane_visible(ORDER[index]) {
+                break;
+            }
+        }
+        self.focus = ORDER[index];
+    }
+
+    /// Show or hide the user list popup, focusing it while it is open.
+    pub fn toggle_users(&mut self) {
+        self.users_popup = !self.users_popup;
+        if self.users_popup {
+            self.focus = Focus::Users;
+        } else if self.focus == Focus::Users && !self.pane_visible(Focus::Users) {
+            self.focus = Focus::Input;
+        }
+    }
+
+    /// Adopt a new terminal size, moving focus off panes that disappeared.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.screen = Screen::for_size(width, height);
+        if !self.pane_visible(self.focus) {
+            self.focus = Focus::Input;
+        }
+    }
+
+    /// Enter on a focused pane: open the selected conversation or user and
+    /// go back to typing.
+    pub fn activate_focused(&mut self) {
+        match self.focus {
+            Focus::Users => {
+                if let Some(username) = self.user_list.get(self.selected_user).cloned() {
+                    self.open_direct(&username);
+                }
+                self.users_popup = false;
+            }
+            Focus::Sidebar | Focus::History | Focus::Input => {}
+        }
+        self.focus = Focus::Input;
+    }
+
+    /// Keys without a binding while a pane other than the input has focus:
+    /// arrows navigate the pane, and typing jumps back to the input.
+    pub fn pane_key(&mut self, key: KeyEvent) {
+        let up = match key.code {
+            KeyCode::Up => true,
+            KeyCode::Down => false,
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.focus = Focus::Input;
+                self.users_popup = false;
+                self.input.insert_str(c.encode_utf8(&mut [0; 4]));
+                return;
+            }
+            _ => return,
+        };
+        match self.focus {
+            Focus::History => self.scroll_lines(if up { 1 } else { -1 }),
+            Focus::Sidebar if up => self.previous_conversation(),
+            Focus::Sidebar => self.next_conversation(),
+            Focus::Users if up => self.selected_user = self.selected_user.saturating_sub(1),
+            Focus::Users => {
+                let last = self.user_list.len().saturating_sub(1);
+                self.selected_user = (self.selected_user + 1).min(last);
+            }
+            Focus::Input => {}
+        }
+    }
+}
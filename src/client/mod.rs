@@ -1,24 +1,44 @@
 pub mod api_client;
 pub mod app_state;
+pub mod attachments;
+pub mod commands;
+pub mod config;
+pub mod conversation;
+pub mod events;
+pub mod headless;
+pub mod input;
+pub mod keymap;
+pub mod layout;
+pub mod render;
+pub mod theme;
 pub mod tui;
 pub mod websocket;
 
-use crate::client::app_state::App;
 use crate::client::app_state::APP_STATE;
+use crate::client::config::Config;
+use crate::client::conversation::unix_millis;
+use crate::client::events::Flow;
 use crate::client::tui::ui;
+use crate::client::websocket::connect_websocket;
+use crate::sdk::VeilClient;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use ratatui::{
-    backend::{Backend, CrosstermBackend},
-    Terminal,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
+use ratatui::{backend::CrosstermBackend, layout::Rect, Terminal};
 use std::{error::Error, io, time::Duration};
-use tokio::runtime::Runtime;
+use tokio::runtime::Handle;
 
-pub fn run_client() -> Result<(), Box<dyn Error>> {
+/// Run the TUI. Blocks the calling thread, so call it from outside the async
+/// executor (e.g. via `tokio::task::block_in_place`).
+pub fn run_client(server_url: String, config: Config) -> Result<(), Box<dyn Error>> {
     // Setup tracing for logging
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::DEBUG)
@@ -28,7 +48,20 @@ pub fn run_client() -> Result<(), Box<dyn Error>> {
     // Initialize terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
+    // Lets terminals that support it report Shift-Enter distinctly from Enter
+    let keyboard_enhancement = supports_keyboard_enhancement().unwrap_or(false);
+    if keyboard_enhancement {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
@@ -36,15 +69,21 @@ pub fn run_client() -> Result<(), Box<dyn Error>> {
     let tick_rate = Duration::from_millis(250);
     let mut last_tick = std::time::Instant::now();
 
-    // Run tokio runtime for async operations
-    let rt = Runtime::new()?;
+    // Reuse the surrounding tokio runtime for async operations
+    let rt = Handle::current();
+    {
+        let mut app = APP_STATE.lock().unwrap();
+        app.client = VeilClient::new(server_url);
+        app.theme = config.theme;
+        app.keymap = config.keymap;
+        let size = terminal.size()?;
+        app.resize(size.width, size.height);
+    }
 
     // Connect to WebSocket on startup (NON-BLOCKING - using tokio::spawn - simplified)
     rt.spawn(async move {
         // Spawn a separate async task for connection
-        let connect_result = App::default()
-            .connect_websocket("ws://localhost:3000/ws".to_string())
-            .await; // Try connection *without* initial lock
+        let connect_result = connect_websocket().await; // Try connection *without* initial lock
 
         // Now, *after* the connection attempt, update status using lock
         if let Err(e) = connect_result {
@@ -59,105 +98,57 @@ pub fn run_client() -> Result<(), Box<dyn Error>> {
     }); // Connection is now attempted in a background task
 
     loop {
-        terminal.draw(|f| ui(f))?;
+        terminal.draw(|f| ui(f, &APP_STATE.lock().unwrap()))?;
 
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                let mut app = APP_STATE.lock().unwrap();
-                match key.code {
-                    KeyCode::Esc => break,
-                    KeyCode::Enter => {
-                        if !app.input.is_empty() {
-                            let input_clone = app.input.clone();
-                            if app.input.starts_with('/') {
-                                rt.block_on(app.process_command(&input_clone));
-                            } else if app.ws_tx.is_some() {
-                                rt.block_on(app.send_message())?;
-                            } else {
-                                app.status =
-                                    "Not connected to WebSocket. Cannot send message.".to_string();
-                            }
-                            app.input.clear();
-                        }
-                    }
-                    KeyCode::Char(c) => {
-                        app.input.push(c);
-                    }
-                    KeyCode::Backspace => {
-                        app.input.pop();
-                    }
-                    _ => {}
-                }
-                if key.code == KeyCode::Char('u') && key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // Example: Ctrl+U to refresh user list (now directly in main thread)
-                    if let Err(e) = rt.block_on(app.fetch_user_list()) {
-                        app.status = format!("Error fetching user list: {}", e);
-                    }
-                }
-                drop(app);
+            let event = event::read()?;
+            if let Event::Resize(width, height) = event {
+                // Redraw from scratch at the new size; the app adjusts its layout below
+                terminal.resize(Rect::new(0, 0, width, height))?;
             }
+            let flow = APP_STATE.lock().unwrap().handle_event(event);
+            if flow == Flow::Quit {
+                break;
+            }
+            rt.block_on(run_requests());
         }
         if last_tick.elapsed() >= tick_rate {
-            let mut app = APP_STATE.lock().unwrap();
-            if app.status == "Fetching user list..." {
-                if let Err(e) = rt.block_on(app.fetch_user_list()) {
-                    app.status = format!("Error fetching user list: {}", e);
-                } else {
-                    app.status = "User list fetched.".to_string();
-                }
-            }
-            drop(app);
             last_tick = std::time::Instant::now();
+            let mut app = APP_STATE.lock().unwrap();
+            app.expire_messages(unix_millis());
+            app.show_transfer_progress();
         }
     }
 
     // Restore terminal
+    if keyboard_enhancement {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
     Ok(())
 }
 
-impl App {
-    pub async fn process_command(&mut self, command: &str) {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if let Some(&cmd) = parts.first() {
-            match cmd {
-                "/users" => {
-                    // No tokio::spawn needed - fetch_user_list is now called directly in main thread
-                    self.status = "Fetching user list...".to_string();
-                }
-                "/create_user" if parts.len() > 1 => {
-                    let username = parts[1..].join(" ");
-                    let username_clone = username.clone();
-                    // No local runtime - call create_user and await directly
-                    if let Err(e) = self.create_user(&username_clone).await {
-                        self.status = format!("Error creating user: {}", e);
-                    }
-                    self.status = format!("Creating user '{}'...", username);
-                }
-                "/delete_user" if parts.len() == 2 => {
-                    if let Ok(user_id) = parts[1].parse::<usize>() {
-                        let user_id_clone = user_id;
-                        // No local runtime - call delete_user and await directly
-                        if let Err(e) = self.delete_user(user_id_clone).await {
-                            self.status = format!("Error deleting user: {}", e);
-                        }
-                        self.status = format!("Deleting user ID '{}'...", user_id);
-                    } else {
-                        self.status = "Invalid user ID format.".to_string();
-                    }
-                }
-                _ => {
-                    self.status = format!("Unknown command: '{}'", command);
-                }
-            }
+/// Run the requests queued by an event without holding the app lock, so the
+/// receive loops keep going meanwhile. Outcomes are applied in order as they
+/// arrive, and any requests they queue are run in turn.
+async fn run_requests() {
+    loop {
+        let requests = std::mem::take(&mut APP_STATE.lock().unwrap().requests);
+        if requests.is_empty() {
+            return;
+        }
+        for request in requests {
+            let update = request.await;
+            update(&mut APP_STATE.lock().unwrap());
         }
     }
 }
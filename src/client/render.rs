@@ -0,0 +1,347 @@
+use crate::client::conversation::{Conversation, Delivery, Entry};
+use crate::client::theme::Theme;
+use chrono::{Local, NaiveDate, TimeZone};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// --- Chat history rendering: timestamps, sender colors, wrapping, markdown ---
+
+/// Width of the `HH:MM ` column in front of every message.
+const TIME_WIDTH: usize = 6;
+
+/// The rows at the bottom of the conversation's history (above any scroll
+/// offset) that fit in a `width` x `height` viewport.
+pub fn history_lines(
+    conversation: &Conversation,
+    width: usize,
+    height: usize,
+    theme: &Theme,
+) -> Vec<Line<'static>> {
+    let history = &conversation.history;
+    let end = history.len() - conversation.scroll.min(history.len());
+    let wanted = height + conversation.scroll_rows;
+
+    // Walk backwards from the newest visible entry until the viewport is full
+    let mut lines = Vec::new();
+    for index in (0..end).rev() {
+        if lines.len() >= wanted {
+            break;
+        }
+        let entry_lines = history_entry(conversation, index, width, theme);
+        lines.extend(entry_lines.into_iter().rev());
+    }
+    lines.truncate(wanted);
+    lines.drain(..conversation.scroll_rows.min(lines.len()));
+    lines.truncate(height);
+    lines.reverse();
+    lines
+}
+
+/// How many rows each history entry takes at `width`, oldest first.
+pub fn entry_heights(conversation: &Conversation, width: usize, theme: &Theme) -> Vec<usize> {
+    (0..conversation.history.len())
+        .map(|index| history_entry(conversation, index, width, theme).len())
+        .collect()
+}
+
+/// Entry `index` of the history as shown there: wrapped, with delivery
+/// ticks, and after a day separator if it starts a new day.
+fn history_entry(
+    conversation: &Conversation,
+    index: usize,
+    width: usize,
+    theme: &Theme,
+) -> Vec<Line<'static>> {
+    let history = &conversation.history;
+    let delivery = match &history[index] {
+        Entry::Message(chat) => chat
+            .id
+            .as_ref()
+            .and_then(|id| conversation.delivery.get(id)),
+        Entry::Notice(_) => None,
+    };
+    let mut lines = entry_lines(&history[index], delivery.copied(), width, theme);
+    if let Some(day) = entry_day(&history[index]) {
+        let previous_day = history.range(..index).rev().find_map(entry_day);
+        if previous_day != Some(day) {
+            lines.insert(0, day_separator(day, width, theme));
+        }
+    }
+    lines
+}
+
+fn entry_day(entry: &Entry) -> Option<NaiveDate> {
+    match entry {
+        Entry::Message(chat) => Some(local_time(chat.sent_at?)?.date_naive()),
+        Entry::Notice(_) => None,
+    }
+}
+
+pub fn local_time(millis: u64) -> Option<chrono::DateTime<Local>> {
+    Local.timestamp_millis_opt(millis as i64).single()
+}
+
+fn day_separator(day: NaiveDate, width: usize, theme: &Theme) -> Line<'static> {
+    let label = format!(" {} ", day.format("%A, %-d %B %Y"));
+    let rule = width.saturating_sub(label.width()) / 2;
+    Line::from(format!("{}{}{}", "─".repeat(rule), label, "─".repeat(rule)))
+        .style(theme.day_separator)
+}
+
+/// Render one history entry, wrapped to `width`. Our own messages end in
+/// ticks showing how far they got.
+pub fn entry_lines(
+    entry: &Entry,
+    delivery: Option<Delivery>,
+    width: usize,
+    theme: &Theme,
+) -> Vec<Line<'static>> {
+    match entry {
+        Entry::Notice(text) => {
+            let style = theme.notice;
+            text.lines()
+                .flat_map(|line| {
+                    let body = vec![Span::styled(line.to_string(), style)];
+                    wrap(vec![Span::styled("-- ", style)], 3, body, width)
+                })
+                .collect()
+        }
+        Entry::Message(chat) => {
+            let time = chat
+                .sent_at
+                .and_then(local_time)
+                .map_or_else(String::new, |t| t.format("%H:%M").to_string());
+            let sender = chat.sender.as_deref().unwrap_or("anonymous");
+            let sender_style = match &chat.sender {
+                Some(name) => theme.sender_style(name),
+                None => theme.anonymous_sender,
+            };
+            let prefix = vec![
+                Span::styled(
+                    format!("{:<width$}", time, width = TIME_WIDTH),
+                    theme.timestamp,
+                ),
+                Span::styled(sender.to_string(), sender_style),
+                Span::raw(" "),
+            ];
+            let indent = TIME_WIDTH + sender.width() + 1;
+
+            let mut body = markdown(&chat.body, theme);
+            if let (Some(delivery), Some(last)) = (delivery, body.last_mut()) {
+                last.push(ticks(delivery, theme));
+            }
+            let mut lines = Vec::new();
+            let mut prefix = Some(prefix);
+            for body in body {
+                let first = prefix
+                    .take()
+                    .unwrap_or_else(|| vec![Span::raw(" ".repeat(indent))]);
+                lines.extend(wrap(first, indent, body, width));
+            }
+            lines
+        }
+    }
+}
+
+fn ticks(delivery: Delivery, theme: &Theme) -> Span<'static> {
+    match delivery {
+        Delivery::Sent => Span::styled(" ✓", theme.receipt),
+        Delivery::Delivered => Span::styled(" ✓✓", theme.receipt),
+        Delivery::Read => Span::styled(" ✓✓", theme.receipt_read),
+    }
+}
+
+/// Greedy word wrap. `first` starts the first row; continuation rows are
+/// indented by `indent` columns so wrapped text lines up with the body.
+fn wrap(
+    first: Vec<Span<'static>>,
+    indent: usize,
+    body: Vec<Span<'static>>,
+    width: usize,
+) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let indent = indent.min(width / 2);
+    let mut lines = Vec::new();
+    let mut used: usize = first.iter().map(|s| s.content.width()).sum();
+    let mut current = first;
+
+    let mut break_line = |current: &mut Vec<Span<'static>>, used: &mut usize| {
+        lines.push(Line::from(std::mem::take(current)));
+        current.push(Span::raw(" ".repeat(indent)));
+        *used = indent;
+    };
+
+    for span in body {
+        for piece in split_words(&span.content) {
+            let piece_width = piece.width();
+            let is_space = piece.chars().all(char::is_whitespace);
+            if used + piece_width > width && used > indent {
+                break_line(&mut current, &mut used);
+                if is_space {
+                    continue; // Don't start a row with the space we broke at
+                }
+            }
+            if used + piece_width <= width {
+                current.push(Span::styled(piece.to_string(), span.style));
+                used += piece_width;
+                continue;
+            }
+            // A word wider than the row: break it between graphemes
+            for grapheme in piece.graphemes(true) {
+                let grapheme_width = grapheme.width();
+                if used + grapheme_width > width && used > indent {
+                    break_line(&mut current, &mut used);
+                }
+                current.push(Span::styled(grapheme.to_string(), span.style));
+                used += grapheme_width;
+            }
+        }
+    }
+    lines.push(Line::from(current));
+    lines
+}
+
+/// Split into alternating runs of whitespace and non-whitespace.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (i, c) in text.char_indices() {
+        let is_space = c.is_whitespace();
+        if in_space.is_some_and(|s| s != is_space) {
+            pieces.push(&text[start..i]);
+            start = i;
+        }
+        in_space = Some(is_space);
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
+// --- Markdown subset ---
+//
+// Supports **bold**, *italics* / _italics_, `inline code`, fenced code
+// blocks, [links](url) and bare http(s) URLs. Anything unmatched is shown
+// literally.
+
+/// Parse a message body into styled spans, one `Vec` per source line.
+pub fn markdown(body: &str, theme: &Theme) -> Vec<Vec<Span<'static>>> {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            lines.push(vec![Span::styled(line.to_string(), theme.code)]);
+        } else {
+            lines.push(inline_markdown(line, theme));
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Vec::new());
+    }
+    lines
+}
+
+fn inline_markdown(line: &str, theme: &Theme) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut text = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let style = |bold: bool, italic: bool| {
+        let mut style = theme.message;
+        if bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if italic {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        style
+    };
+    fn flush(spans: &mut Vec<Span<'static>>, text: &mut String, style: Style) {
+        if !text.is_empty() {
+            spans.push(Span::styled(std::mem::take(text), style));
+        }
+    }
+
+    let mut i = 0;
+    let mut previous: Option<char> = None;
+    while i < line.len() {
+        let rest = &line[i..];
+        let current = style(bold, italic);
+
+        if let Some(end) = rest.strip_prefix('`').and_then(|r| r.find('`')) {
+            flush(&mut spans, &mut text, current);
+            spans.push(Span::styled(rest[1..1 + end].to_string(), theme.code));
+            i += end + 2;
+            previous = Some('`');
+            continue;
+        }
+        if rest.starts_with("**") && (bold || rest[2..].contains("**")) {
+            flush(&mut spans, &mut text, current);
+            bold = !bold;
+            i += 2;
+            continue;
+        }
+        if rest.starts_with('*') && (italic || rest[1..].contains('*')) {
+            flush(&mut spans, &mut text, current);
+            italic = !italic;
+            i += 1;
+            continue;
+        }
+        // Underscores only count at word edges so snake_case stays intact
+        if let Some(after) = rest.strip_prefix('_') {
+            let next = after.chars().next();
+            let opens =
+                !italic && !previous.is_some_and(char::is_alphanumeric) && after.contains('_');
+            let closes = italic && !next.is_some_and(char::is_alphanumeric);
+            if opens || closes {
+                flush(&mut spans, &mut text, current);
+                italic = !italic;
+                i += 1;
+                previous = Some('_');
+                continue;
+            }
+        }
+        if let Some((label, url, len)) = parse_link(rest) {
+            flush(&mut spans, &mut text, current);
+            spans.push(Span::styled(label.to_string(), theme.link));
+            spans.push(Span::styled(format!(" <{}>", url), theme.link_url));
+            i += len;
+            continue;
+        }
+        if rest.starts_with("http://") || rest.starts_with("https://") {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            flush(&mut spans, &mut text, current);
+            spans.push(Span::styled(rest[..end].to_string(), theme.link));
+            i += end;
+            continue;
+        }
+
+        let c = rest.chars().next().expect("i is within the line");
+        text.push(c);
+        previous = Some(c);
+        i += c.len_utf8();
+    }
+    flush(&mut spans, &mut text, style(bold, italic));
+    spans
+}
+
+/// `[label](url)` at the start of `text`: returns label, url and the length
+/// consumed.
+fn parse_link(text: &str) -> Option<(&str, &str, usize)> {
+    let label_end = text.strip_prefix('[')?.find("](")? + 1;
+    let url_start = label_end + 2;
+    let url_end = url_start + text[url_start..].find(')')?;
+    let url = &text[url_start..url_end];
+    if url.is_empty() || url.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((&text[1..label_end], url, url_end + 1))
+}
@@ -0,0 +1,338 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+// --- Color themes ---
+//
+// Every style the TUI draws with lives here, so a theme file can restyle the
+// whole interface. Theme files are TOML:
+//
+//     base = "monochrome"                 # Built-in theme to start from
+//     user_colors = ["red", "#5f87ff"]    # Sender name palette
+//
+//     [styles]
+//     timestamp = { fg = "gray" }
+//     link = { fg = "blue", modifiers = ["underlined"] }
+//
+// A style given in `[styles]` replaces the base style entirely.
+
+/// Names accepted by `Theme::builtin`.
+pub const BUILTIN_THEMES: [&str; 3] = ["default", "high-contrast", "monochrome"];
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub border: Style,
+    pub focused_border: Style, // Border of the pane that has focus
+    pub title: Style,
+    pub status_label: Style,   // "Status: " in the status bar
+    pub sidebar_active: Style, // Selected conversation
+    pub sidebar_unread: Style, // Conversations with unread messages
+    pub header_target: Style,  // Conversation name in the header
+    pub header_details: Style, // Members and encryption state
+    pub input_hint: Style,     // Completions and command errors
+    pub new_messages: Style,   // "N new messages below"
+    pub more_below: Style,     // "more below" while scrolled up
+    pub timestamp: Style,
+    pub day_separator: Style,
+    pub notice: Style, // Local notices such as /help output
+    pub sender: Style, // Sender names; the color comes from `user_colors`
+    pub anonymous_sender: Style,
+    pub message: Style, // Message body text
+    pub code: Style,
+    pub link: Style,
+    pub link_url: Style,        // The ` <url>` after a [label](url) link
+    pub presence_online: Style, // Status dots in the user list
+    pub presence_away: Style,
+    pub presence_offline: Style,
+    pub receipt: Style, // Ticks after own messages: sent, delivered
+    pub receipt_read: Style,
+    pub typing: Style,           // "alice is typing…" in the header
+    pub user_colors: Vec<Color>, // Picked per sender; empty keeps `sender` as is
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            border: Style::default(),
+            focused_border: Style::default().fg(Color::Cyan),
+            title: Style::default(),
+            status_label: Style::default().fg(Color::Yellow),
+            sidebar_active: Style::default().add_modifier(Modifier::REVERSED),
+            sidebar_unread: Style::default().add_modifier(Modifier::BOLD),
+            header_target: Style::default().add_modifier(Modifier::BOLD),
+            header_details: Style::default(),
+            input_hint: Style::default().fg(Color::Red),
+            new_messages: Style::default().fg(Color::Yellow),
+            more_below: Style::default(),
+            timestamp: Style::default().fg(Color::DarkGray),
+            day_separator: Style::default().fg(Color::DarkGray),
+            notice: Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+            sender: Style::default().add_modifier(Modifier::BOLD),
+            anonymous_sender: Style::default().fg(Color::DarkGray),
+            message: Style::default(),
+            code: Style::default().fg(Color::Cyan),
+            link: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::UNDERLINED),
+            link_url: Style::default().fg(Color::DarkGray),
+            presence_online: Style::default().fg(Color::Green),
+            presence_away: Style::default().fg(Color::Yellow),
+            presence_offline: Style::default().fg(Color::DarkGray),
+            receipt: Style::default().fg(Color::DarkGray),
+            receipt_read: Style::default().fg(Color::Cyan),
+            typing: Style::default().add_modifier(Modifier::ITALIC),
+            user_colors: vec![
+                Color::Red,
+                Color::Green,
+                Color::Yellow,
+                Color::Blue,
+                Color::Magenta,
+                Color::Cyan,
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightBlue,
+                Color::LightMagenta,
+            ],
+        }
+    }
+}
+
+impl Theme {
+    /// Bright colors only and no dim text, for low-quality displays and
+    /// low vision.
+    pub fn high_contrast() -> Theme {
+        let bright = |color| Style::default().fg(color).add_modifier(Modifier::BOLD);
+        Theme {
+            border: Style::default().fg(Color::White),
+            focused_border: bright(Color::LightYellow),
+            title: bright(Color::White),
+            status_label: bright(Color::LightYellow),
+            sidebar_active: Style::default()
+                .fg(Color::Black)
+                .bg(Color::White)
+                .add_modifier(Modifier::BOLD),
+            sidebar_unread: bright(Color::LightYellow),
+            header_target: bright(Color::White),
+            header_details: Style::default().fg(Color::White),
+            input_hint: bright(Color::LightRed),
+            new_messages: bright(Color::LightYellow),
+            more_below: bright(Color::White),
+            timestamp: Style::default().fg(Color::White),
+            day_separator: bright(Color::White),
+            notice: Style::default()
+                .fg(Color::LightCyan)
+                .add_modifier(Modifier::ITALIC),
+            sender: Style::default().add_modifier(Modifier::BOLD),
+            anonymous_sender: bright(Color::White),
+            message: Style::default().fg(Color::White),
+            code: bright(Color::LightCyan),
+            link: Style::default()
+                .fg(Color::LightBlue)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            link_url: Style::default().fg(Color::White),
+            presence_online: bright(Color::LightGreen),
+            presence_away: bright(Color::LightYellow),
+            presence_offline: Style::default().fg(Color::White),
+            receipt: Style::default().fg(Color::White),
+            receipt_read: bright(Color::LightCyan),
+            typing: bright(Color::White),
+            user_colors: vec![
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightYellow,
+                Color::LightBlue,
+                Color::LightMagenta,
+                Color::LightCyan,
+            ],
+        }
+    }
+
+    /// No colors at all; emphasis comes from text attributes alone.
+    pub fn monochrome() -> Theme {
+        let with = |modifier| Style::default().add_modifier(modifier);
+        Theme {
+            border: Style::default(),
+            focused_border: with(Modifier::BOLD),
+            title: Style::default(),
+            status_label: with(Modifier::BOLD),
+            sidebar_active: with(Modifier::REVERSED),
+            sidebar_unread: with(Modifier::BOLD),
+            header_target: with(Modifier::BOLD),
+            header_details: Style::default(),
+            input_hint: with(Modifier::BOLD),
+            new_messages: with(Modifier::BOLD | Modifier::REVERSED),
+            more_below: Style::default(),
+            timestamp: with(Modifier::DIM),
+            day_separator: with(Modifier::DIM),
+            notice: with(Modifier::ITALIC),
+            sender: with(Modifier::BOLD),
+            anonymous_sender: with(Modifier::DIM),
+            message: Style::default(),
+            code: with(Modifier::REVERSED),
+            link: with(Modifier::UNDERLINED),
+            link_url: with(Modifier::DIM),
+            presence_online: with(Modifier::BOLD),
+            presence_away: Style::default(),
+            presence_offline: with(Modifier::DIM),
+            receipt: with(Modifier::DIM),
+            receipt_read: with(Modifier::BOLD),
+            typing: with(Modifier::ITALIC),
+            user_colors: Vec::new(),
+        }
+    }
+
+    /// One of `BUILTIN_THEMES` by name.
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "monochrome" => Some(Theme::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// A built-in theme name, or the path of a theme file.
+    pub fn load(name_or_path: &str) -> Result<Theme, Box<dyn Error>> {
+        match Theme::builtin(name_or_path) {
+            Some(theme) => Ok(theme),
+            None => Theme::from_file(Path::new(name_or_path)),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Theme, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read theme {}: {}", path.display(), e))?;
+        Theme::parse(&text).map_err(|e| format!("invalid theme {}: {}", path.display(), e).into())
+    }
+
+    /// Parse the contents of a theme file.
+    pub fn parse(text: &str) -> Result<Theme, Box<dyn Error>> {
+        let file: ThemeFile = toml::from_str(text)?;
+        let base = file.base.as_deref().unwrap_or("default");
+        let mut theme = Theme::builtin(base).ok_or_else(|| {
+            format!(
+                "unknown base theme '{}', expected one of: {}",
+                base,
+                BUILTIN_THEMES.join(", ")
+            )
+        })?;
+        for (name, spec) in &file.styles {
+            let style = spec.to_style().map_err(|e| format!("{}: {}", name, e))?;
+            *theme
+                .style_mut(name)
+                .ok_or_else(|| format!("unknown style '{}'", name))? = style;
+        }
+        if let Some(colors) = &file.user_colors {
+            theme.user_colors = colors
+                .iter()
+                .map(|c| parse_color(c))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(theme)
+    }
+
+    /// The `sender` style colored for this username.
+    pub fn sender_style(&self, username: &str) -> Style {
+        if self.user_colors.is_empty() {
+            return self.sender;
+        }
+        // FNV-1a rather than std's hasher, whose output may change between releases
+        let hash = username.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        let color = self.user_colors[(hash % self.user_colors.len() as u64) as usize];
+        self.sender.fg(color)
+    }
+
+    fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "border" => &mut self.border,
+            "focused_border" => &mut self.focused_border,
+            "title" => &mut self.title,
+            "status_label" => &mut self.status_label,
+            "sidebar_active" => &mut self.sidebar_active,
+            "sidebar_unread" => &mut self.sidebar_unread,
+            "header_target" => &mut self.header_target,
+            "header_details" => &mut self.header_details,
+            "input_hint" => &mut self.input_hint,
+            "new_messages" => &mut self.new_messages,
+            "more_below" => &mut self.more_below,
+            "timestamp" => &mut self.timestamp,
+            "day_separator" => &mut self.day_separator,
+            "notice" => &mut self.notice,
+            "sender" => &mut self.sender,
+            "anonymous_sender" => &mut self.anonymous_sender,
+            "message" => &mut self.message,
+            "code" => &mut self.code,
+            "link" => &mut self.link,
+            "link_url" => &mut self.link_url,
+            "presence_online" => &mut self.presence_online,
+            "presence_away" => &mut self.presence_away,
+            "presence_offline" => &mut self.presence_offline,
+            "receipt" => &mut self.receipt,
+            "receipt_read" => &mut self.receipt_read,
+            "typing" => &mut self.typing,
+            _ => return None,
+        })
+    }
+}
+
+// --- Theme file ---
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    #[serde(default)]
+    styles: HashMap<String, StyleSpec>,
+    user_colors: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+impl StyleSpec {
+    fn to_style(&self) -> Result<Style, String> {
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(parse_color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(parse_color(bg)?);
+        }
+        for name in &self.modifiers {
+            style = style.add_modifier(parse_modifier(name)?);
+        }
+        Ok(style)
+    }
+}
+
+/// Color names ("red", "light-blue"), `#rrggbb`, or a 256-color index.
+fn parse_color(text: &str) -> Result<Color, String> {
+    Color::from_str(text).map_err(|_| format!("unknown color '{}'", text))
+}
+
+fn parse_modifier(name: &str) -> Result<Modifier, String> {
+    Ok(match name {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" => Modifier::UNDERLINED,
+        "reversed" => Modifier::REVERSED,
+        "crossed_out" => Modifier::CROSSED_OUT,
+        "slow_blink" => Modifier::SLOW_BLINK,
+        "hidden" => Modifier::HIDDEN,
+        _ => return Err(format!("unknown modifier '{}'", name)),
+    })
+}
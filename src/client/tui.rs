@@ -1,61 +1,259 @@
-use crate::client::app_state::APP_STATE;
+use crate::client::app_state::App;
+use crate::client::layout::{Focus, Screen};
+use crate::client::render::history_lines;
+use crate::client::theme::Theme;
+use crate::sdk::PresenceStatus;
 use ratatui::{
-    backend::Backend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
     Frame,
 };
+use std::time::Instant;
 
-pub fn ui(f: &mut Frame) {
-    let app_ref = APP_STATE.lock().unwrap();
-    let app = &*app_ref;
+/// Rows the input bar grows to before it starts scrolling.
+const MAX_INPUT_ROWS: usize = 5;
 
+const SIDEBAR_WIDTH: u16 = 24;
+
+/// Draw the whole interface for `app`.
+pub fn ui(f: &mut Frame, app: &App) {
+    let area = f.area();
+    let screen = Screen::for_size(area.width, area.height);
+
+    // Only spend rows and columns on margins when there are plenty
+    let margin = u16::from(screen.wide && screen.tall);
+    let main = if screen.wide {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(margin)
+            .constraints([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(1)])
+            .split(area);
+        render_sidebar(f, app, columns[0]);
+        columns[1]
+    } else {
+        area
+    };
+
+    // Bordered bars take three rows each; on short screens they get one
+    let bar_rows = if screen.tall { 3 } else { 1 };
+    let users_rows = if screen.tall { 5 } else { 0 };
+    // The input bar grows with multi-line input, up to MAX_INPUT_ROWS
+    let input_rows = app.input.line_count().min(MAX_INPUT_ROWS) as u16;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .margin(2)
-        .constraints(
-            [
-                Constraint::Length(3),
-                Constraint::Min(1),
-                Constraint::Length(3),
-                Constraint::Length(5),
-            ]
-            .as_ref(),
-        )
-        .split(f.size());
+        .constraints([
+            Constraint::Length(bar_rows),
+            Constraint::Length(bar_rows),
+            Constraint::Min(1),
+            Constraint::Length(input_rows + 2),
+            Constraint::Length(users_rows),
+        ])
+        .split(main);
 
-    let status_bar = Paragraph::new(Line::from(vec![
-        Span::styled("Status: ", Style::default().fg(Color::Yellow)),
-        Span::raw(&app.status),
-    ]))
-    .block(Block::default().borders(Borders::ALL).title("Status"));
-    f.render_widget(status_bar, chunks[0]);
-
-    let messages: Vec<ListItem> = app
-        .messages
+    render_status(f, app, chunks[0], screen);
+    render_header(f, app, chunks[1], screen);
+    render_history(f, app, chunks[2]);
+    render_input(f, app, chunks[3], input_rows);
+    if screen.tall {
+        render_users(f, app, chunks[4]);
+    }
+    if app.users_popup {
+        let popup = centered(area, 32, app.user_list.len().max(1) as u16 + 2);
+        f.render_widget(Clear, popup);
+        render_users(f, app, popup);
+    }
+}
+
+fn render_sidebar(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let conversation_items: Vec<ListItem> = app
+        .conversations
         .iter()
-        .map(|m| ListItem::new(Line::from(Span::raw(m))))
+        .enumerate()
+        .map(|(i, conversation)| {
+            let style = if i == app.active {
+                theme.sidebar_active
+            } else if conversation.unread > 0 {
+                theme.sidebar_unread
+            } else {
+                theme.message
+            };
+            ListItem::new(Line::from(Span::styled(conversation.label(), style)))
+        })
         .collect();
+    let sidebar = List::new(conversation_items)
+        .block(block(theme, app.focus == Focus::Sidebar).title("Conversations"));
+    f.render_widget(sidebar, area);
+}
+
+fn render_status(f: &mut Frame, app: &App, area: Rect, screen: Screen) {
+    let theme = &app.theme;
+    let status_bar = Paragraph::new(Line::from(vec![
+        Span::styled("Status: ", theme.status_label),
+        Span::styled(&app.status, theme.message),
+    ]));
+    let mut inner = area;
+    if screen.tall {
+        let frame = block(theme, false).title("Status");
+        inner = frame.inner(area);
+        f.render_widget(frame, area);
+    }
+    f.render_widget(status_bar, inner);
+    // Round-trip time to the server, right-aligned over the end of the status
+    if let Some(rtt) = app.latency {
+        let latency = Span::styled(format!(" {} ms", rtt.as_millis()), theme.status_label);
+        f.render_widget(Paragraph::new(latency).alignment(Alignment::Right), inner);
+    }
+}
 
-    let history =
-        List::new(messages).block(Block::default().borders(Borders::ALL).title("Chat History"));
-    f.render_widget(history, chunks[1]);
+fn render_header(f: &mut Frame, app: &App, area: Rect, screen: Screen) {
+    let theme = &app.theme;
+    let conversation = app.active_conversation();
+    let mut spans = vec![
+        Span::styled(conversation.target.to_string(), theme.header_target),
+        Span::styled(
+            format!(
+                " · {} · {}",
+                conversation.member_label(),
+                conversation.encryption_label()
+            ),
+            theme.header_details,
+        ),
+    ];
+    if let Some(timer) = conversation.timer_label() {
+        spans.push(Span::styled(format!(" · {}", timer), theme.header_details));
+    }
+    if let Some(typing) = conversation.typing_label(Instant::now()) {
+        spans.push(Span::styled(format!(" · {}", typing), theme.typing));
+    }
+    // Without the sidebar, unread messages elsewhere would go unnoticed
+    if !screen.wide {
+        let unread: usize = app.conversations.iter().map(|c| c.unread).sum();
+        if unread > 0 {
+            spans.push(Span::styled(
+                format!(" · {} unread elsewhere", unread),
+                theme.sidebar_unread,
+            ));
+        }
+    }
+    let mut header = Paragraph::new(Line::from(spans));
+    if screen.tall {
+        header = header.block(block(theme, false));
+    }
+    f.render_widget(header, area);
+}
+
+fn render_history(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let conversation = app.active_conversation();
+    let history_height = area.height.saturating_sub(2) as usize; // Minus borders
+    let history_width = area.width.saturating_sub(2) as usize;
+    app.history_height.set(history_height);
+    app.history_width.set(history_width);
+    let messages = history_lines(conversation, history_width, history_height, theme);
 
-    // Type hint added here: `Paragraph::new(app.input.as_str())` - using `.as_str()` to get &str
-    let input_bar = Paragraph::new(app.input.as_str()) // Explicitly use .as_str() to get &str
-        .block(Block::default().borders(Borders::ALL).title("Input"));
-    f.render_widget(input_bar, chunks[2]);
+    let mut history_block = block(theme, app.focus == Focus::History).title("Chat History");
+    if conversation.unseen_below > 0 {
+        history_block = history_block.title_bottom(
+            Line::from(format!(
+                " ↓ {} new messages below ",
+                conversation.unseen_below
+            ))
+            .style(theme.new_messages)
+            .right_aligned(),
+        );
+    } else if conversation.is_scrolled() {
+        history_block = history_block.title_bottom(
+            Line::from(" ↓ more below ")
+                .style(theme.more_below)
+                .right_aligned(),
+        );
+    }
+    let history = Paragraph::new(messages).block(history_block);
+    f.render_widget(history, area);
+}
+
+fn render_input(f: &mut Frame, app: &App, area: Rect, input_rows: u16) {
+    let theme = &app.theme;
+    // Scroll the input so the cursor stays inside the box
+    let (cursor_col, cursor_row) = app.input.cursor_position();
+    let inner_width = area.width.saturating_sub(2);
+    let scroll = (
+        cursor_row.saturating_sub(input_rows - 1),
+        cursor_col.saturating_sub(inner_width.saturating_sub(1)),
+    );
+    let focused = app.focus == Focus::Input;
+    let mut input_block = block(theme, focused).title("Input");
+    if let Some(hint) = &app.input_hint {
+        input_block =
+            input_block.title_bottom(Line::from(format!(" {} ", hint)).style(theme.input_hint));
+    }
+    let input_bar = Paragraph::new(app.input.as_str())
+        .style(theme.message)
+        .scroll(scroll)
+        .block(input_block);
+    f.render_widget(input_bar, area);
+    // The cursor only shows while typing goes to the input
+    if focused {
+        f.set_cursor_position((
+            area.x + 1 + cursor_col - scroll.1,
+            area.y + 1 + cursor_row - scroll.0,
+        ));
+    }
+}
 
+fn render_users(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let focused = app.focus == Focus::Users;
     let user_list_items: Vec<ListItem> = app
         .user_list
         .iter()
-        .map(|user| ListItem::new(Line::from(Span::raw(user))))
+        .map(|user| {
+            let status = app.presence.get(user).copied().unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                presence_dot(theme, status),
+                Span::styled(user, theme.message),
+            ]))
+        })
         .collect();
-    let user_list_widget =
-        List::new(user_list_items).block(Block::default().borders(Borders::ALL).title("Users"));
-    f.render_widget(user_list_widget, chunks[3]);
+    let user_list_widget = List::new(user_list_items)
+        .highlight_style(theme.sidebar_active)
+        .block(block(theme, focused).title("Users"));
+    let mut state = ListState::default().with_selected(focused.then_some(app.selected_user));
+    f.render_stateful_widget(user_list_widget, area, &mut state);
+}
+
+/// Shapes differ as well as colors, so themes without color still tell the
+/// states apart.
+fn presence_dot(theme: &Theme, status: PresenceStatus) -> Span<'static> {
+    match status {
+        PresenceStatus::Online => Span::styled("● ", theme.presence_online),
+        PresenceStatus::Away => Span::styled("◐ ", theme.presence_away),
+        PresenceStatus::Offline => Span::styled("○ ", theme.presence_offline),
+    }
+}
+
+/// A `width` x `height` rectangle centered in `area`, clipped to fit.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
 
-    drop(app_ref);
+/// A bordered block in the theme's styles, highlighted when it has focus.
+fn block(theme: &Theme, focused: bool) -> Block<'static> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(if focused {
+            theme.focused_border
+        } else {
+            theme.border
+        })
+        .title_style(theme.title)
 }
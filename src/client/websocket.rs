@@ -1,103 +1,234 @@
 use crate::client::app_state::{App, APP_STATE};
-// src/client/websocket.rs
-use futures::stream::{SplitSink, SplitStream, StreamExt};
-use std::{io::Error as IoError, sync::Arc, sync::Mutex, thread, time::Duration};
-use tokio::net::TcpStream;
-use tokio::runtime::Runtime;
-use tokio::time::sleep;
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{error::Error as WsError, Message},
-    MaybeTlsStream,
-    WebSocketStream, // Make sure MaybeTlsStream is imported
+use crate::sdk::{
+    encode_payload, ClientError, Event, EventStream, HistoryQuery, MessagePayload, ReceiptBody,
+    Target, VeilClient,
 };
-// Import App and APP_STATE
-use futures::sink::SinkExt;
-// Import SinkExt trait for .send()
-// Removed incorrect import:
-// use tokio_stream::wrappers::TcpStream as TokioTcpStream; // Import Tokio's TcpStream wrapper
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+use std::sync::MutexGuard;
+use std::time::{Duration, Instant};
 
-impl App {
-    pub async fn connect_websocket(&mut self, server_address: String) -> Result<(), WsError> {
-        match connect_async(&server_address).await {
-            Ok((ws_stream, _response)) => {
-                self.status = format!("Connected to {}", server_address);
-                tracing::info!("WebSocket handshake has been successfully completed");
-                // Corrected type is already in place:
-                let (ws_sender, ws_receiver) = ws_stream.split();
-                self.ws_tx = Some(ws_sender);
-                self.start_receiving_messages(ws_receiver);
-                Ok(())
-            }
-            Err(err) => {
-                self.status = format!("Connection error: {}", err);
-                tracing::error!("Error during WebSocket handshake: {}", err);
-                Err(err)
+/// Typing frames are sent at most this often per conversation.
+const TYPING_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Connect the global TUI state to the server and start the receive loop.
+pub async fn connect_websocket() -> Result<(), ClientError> {
+    // Connect on a clone so the lock isn't held during the handshake
+    let mut client = APP_STATE.lock().unwrap().client.clone();
+    let events = client.connect().await?;
+    let groups = client.list_groups().await?;
+
+    let queries = {
+        let mut app = APP_STATE.lock().unwrap();
+        app.status = format!("Connected to {}", client.server_url());
+        app.client = client.clone();
+        app.sync_groups(groups);
+        app.presence.clear(); // The new connection is sent everyone's presence
+        app.connection_id += 1;
+        tokio::spawn(receive_messages(events, app.connection_id));
+        app.history_queries()
+    };
+    request_history(&client, queries).await;
+    Ok(())
+}
+
+/// The app, unless a reconnect has replaced connection `connection_id`.
+fn current_app(connection_id: u64) -> Option<MutexGuard<'static, App>> {
+    let app = APP_STATE.lock().unwrap();
+    (app.connection_id == connection_id).then_some(app)
+}
+
+async fn receive_messages(mut events: EventStream, connection_id: u64) {
+    while let Some(event) = events.next().await {
+        // Network follow-ups run after letting go of the lock
+        let follow_up: Option<BoxFuture<'static, ()>> = {
+            // A replaced connection must not touch the app; its events would
+            // arrive twice, once from each stream
+            let Some(mut app) = current_app(connection_id) else {
+                return;
+            };
+            match event {
+                Ok(Event::Message(chat)) => {
+                    app.receive_message(chat);
+                    let (client, receipts) = (app.client.clone(), std::mem::take(&mut app.outbox));
+                    Some(Box::pin(
+                        async move { send_receipts(&client, receipts).await },
+                    ))
+                }
+                Ok(Event::Error(error)) => {
+                    app.status = format!("Server refused a message: {}", error.message);
+                    if let Some(ms) = error.retry_after_ms {
+                        app.status += &format!(" (retry in {:.1}s)", ms as f64 / 1000.0);
+                    }
+                    None
+                }
+                Ok(Event::Presence(presence)) => {
+                    app.presence.insert(presence.username, presence.status);
+                    None
+                }
+                Ok(Event::History(page)) => {
+                    let group = page.group.clone();
+                    let client = app.client.clone();
+                    app.receive_history(page).map(|query| {
+                        Box::pin(
+                            async move { request_history(&client, vec![(group, query)]).await },
+                        ) as BoxFuture<'static, ()>
+                    })
+                }
+                Ok(Event::Typing(typing)) => {
+                    app.receive_typing(typing);
+                    None
+                }
+                Ok(Event::Receipt { body, .. }) => {
+                    app.receive_receipt(body);
+                    None
+                }
+                Ok(Event::Latency(rtt)) => {
+                    app.latency = Some(rtt);
+                    None
+                }
+                Ok(Event::Unknown(text)) => {
+                    tracing::debug!("Ignoring unknown frame: {}", text);
+                    None
+                }
+                Ok(Event::Closed { code, reason }) => {
+                    tracing::warn!("Server closed the WebSocket: {} {}", code, reason);
+                    app.latency = None;
+                    app.status = if reason.is_empty() {
+                        format!("Server closed the connection (code {}).", code)
+                    } else {
+                        format!("Server closed the connection: {}.", reason)
+                    };
+                    return;
+                }
+                Err(e) => {
+                    tracing::error!("Error receiving message: {}", e);
+                    app.latency = None;
+                    app.status = format!("WebSocket receive error: {}", e);
+                    return;
+                }
             }
+        };
+        if let Some(follow_up) = follow_up {
+            follow_up.await;
         }
     }
+    if let Some(mut app) = current_app(connection_id) {
+        app.latency = None;
+        app.status = "Disconnected from server.".to_string();
+    }
+    tracing::warn!("WebSocket receive task ended.");
+}
 
-    fn start_receiving_messages(
-        &mut self,
-        ws_receiver: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-    ) {
-        let messages_clone = Arc::new(Mutex::new(self.messages.clone()));
-        let status_clone = Arc::new(Mutex::new(self.status.clone()));
-        let user_list_clone = Arc::new(Mutex::new(self.user_list.clone()));
-        let app_messages_weak = Arc::downgrade(&messages_clone);
-        let app_status_weak = Arc::downgrade(&status_clone);
-        let app_user_list_weak = Arc::downgrade(&user_list_clone);
+async fn request_history(client: &VeilClient, queries: Vec<(String, HistoryQuery)>) {
+    for (group, query) in queries {
+        if let Err(e) = client.request_history(&group, &query).await {
+            tracing::debug!("Could not request the history of {}: {}", group, e);
+        }
+    }
+}
 
-        thread::spawn(move || {
-            let runtime = Runtime::new().unwrap();
-            runtime.block_on(async move {
-                let mut receiver = ws_receiver;
-                while let Some(result_msg) = receiver.next().await {
-                    match result_msg {
-                        Ok(msg) => {
-                            if let Message::Text(text_bytes) = msg {
-                                if let Some(messages_arc) = app_messages_weak.upgrade() {
-                                    let mut messages = messages_arc.lock().unwrap();
-                                    // Corrected: Dereference &text_bytes to get &[u8]
-                                    messages.push(
-                                        String::from_utf8_lossy(text_bytes.as_ref()).into_owned(),
-                                    );
-                                    if messages.len() > 10 {
-                                        messages.remove(0);
-                                    }
-                                    drop(messages);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Error receiving message: {}", e);
-                            if let Some(status_arc) = app_status_weak.upgrade() {
-                                let mut status = status_arc.lock().unwrap();
-                                *status = format!("WebSocket receive error: {}", e);
-                                drop(status);
-                            }
-                            break;
-                        }
-                    }
-                    sleep(Duration::from_millis(10)).await;
+async fn send_receipts(client: &VeilClient, receipts: Vec<(String, ReceiptBody)>) {
+    for (to, body) in receipts {
+        if let Err(e) = client.send_receipt(&to, &body).await {
+            tracing::debug!("Could not send a receipt to {}: {}", to, e);
+        }
+    }
+}
+
+impl App {
+    /// Send a submitted input line to the active conversation, with its
+    /// timer if one is set there.
+    pub fn send_message(&mut self, text: String) {
+        let conversation = self.active_conversation();
+        let target = conversation.target.clone();
+        if matches!(target, Target::Direct(_)) && self.client.user().is_none() {
+            self.status = "Log in with /login to send direct messages.".to_string();
+            return;
+        }
+        let body = encode_payload(&MessagePayload {
+            text,
+            expires_after_secs: conversation.timer,
+            attachment: None,
+        });
+        let client = self.client.clone();
+        self.request(
+            async move { client.send_to(&target, &body).await },
+            |app, sent| {
+                if let Err(e) = sent {
+                    app.status = format!("Error sending message: {}", e);
                 }
-                if let Some(status_arc) = app_status_weak.upgrade() {
-                    let mut status = status_arc.lock().unwrap();
-                    *status = "Disconnected from server.".to_string();
-                    drop(status);
+            },
+        );
+    }
+
+    /// Tell the active conversation we are typing, unless we did so lately.
+    /// Anonymous users can't be told apart, so they don't.
+    pub fn send_typing(&mut self) {
+        if self.client.user().is_none() || !self.client.is_connected() {
+            return;
+        }
+        let target = self.active_conversation().target.clone();
+        let recent = self
+            .typing_sent
+            .as_ref()
+            .is_some_and(|(sent_to, at)| *sent_to == target && at.elapsed() < TYPING_INTERVAL);
+        if recent {
+            return;
+        }
+        self.typing_sent = Some((target.clone(), Instant::now()));
+        let client = self.client.clone();
+        self.request(
+            async move { client.send_typing(&target).await },
+            |_, sent| {
+                if let Err(e) = sent {
+                    tracing::debug!("Could not send a typing frame: {}", e);
                 }
-                tracing::warn!("WebSocket receive task ended.");
-            });
-        });
+            },
+        );
     }
 
-    pub async fn send_message(&mut self) -> Result<(), WsError> {
-        if let Some(sender) = &mut self.ws_tx {
-            let msg_text: String = self.input.drain(..).collect();
-            // Corrected: Convert String to Utf8Bytes using .into()
-            let msg = Message::Text(msg_text.into());
-            sender.send(msg).await?;
+    /// Send the receipts queued so far.
+    pub fn flush_receipts(&mut self) {
+        if self.outbox.is_empty() {
+            return;
         }
-        Ok(())
+        let (client, receipts) = (self.client.clone(), std::mem::take(&mut self.outbox));
+        self.request(
+            async move { send_receipts(&client, receipts).await },
+            |_, ()| {},
+        );
+    }
+
+    /// Replace the current connection, e.g. after logging in.
+    pub fn reconnect(&mut self) {
+        let mut client = self.client.clone();
+        let call = async move {
+            let events = match client.disconnect().await {
+                Ok(()) => client.connect().await,
+                Err(e) => Err(e),
+            };
+            (client, events)
+        };
+        self.request(call, |app, (client, events)| {
+            // Keep the client even on failure: the old connection is gone
+            app.client = client;
+            let events = match events {
+                Ok(events) => events,
+                Err(e) => {
+                    app.status = format!("Error reconnecting: {}", e);
+                    return;
+                }
+            };
+            app.connection_id += 1;
+            app.latency = None;
+            app.presence.clear();
+            tokio::spawn(receive_messages(events, app.connection_id));
+            let (client, queries) = (app.client.clone(), app.history_queries());
+            app.request(
+                async move { request_history(&client, queries).await },
+                |_, ()| {},
+            );
+        });
     }
 }
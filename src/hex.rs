@@ -0,0 +1,18 @@
+//! Lowercase hex, as used for blob hashes, attachment keys and password
+//! hashes. Shared by every feature, so it lives outside them.
+
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The bytes spelled by `text`, in either case; none unless it is all hex
+/// digits in pairs.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,18 @@
+//! Project Veil: end-to-end encrypted group chat.
+//!
+//! [`proto`] holds the wire types and [`hex`] their hex encoding; both are
+//! always available. The rest is gated by cargo features:
+//!
+//! - `sdk`: [`sdk::VeilClient`], an async client for bots and services
+//! - `client`: the TUI and headless commands (implies `sdk`)
+//! - `server`: the axum server
+
+pub mod hex;
+pub mod proto;
+
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "sdk")]
+pub mod sdk;
+#[cfg(feature = "server")]
+pub mod server;
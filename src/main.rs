@@ -1,5 +1,7 @@
-mod client;
-mod server;
+#[cfg(feature = "client")]
+use project_veil::client;
+#[cfg(feature = "server")]
+use project_veil::server;
 
 use clap::{Parser, Subcommand};
 
@@ -7,6 +9,10 @@ use clap::{Parser, Subcommand};
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
 struct Cli {
+    /// Base URL of the Veil server (client commands only)
+    #[clap(long, global = true, default_value = "http://localhost:3000")]
+    server: String,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -14,30 +20,113 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Run the Veil server
-    Server,
+    Server {
+        /// Config file with admins and rate limits
+        #[clap(long)]
+        config: Option<std::path::PathBuf>,
+    },
+    /// Read a password from stdin and print its hash, for `admins` in the server config
+    HashPassword,
     /// Run the Veil client
-    Client,
+    Client {
+        /// Config file (default: ~/.config/veil/config.toml)
+        #[clap(long)]
+        config: Option<std::path::PathBuf>,
+        /// Color theme: default, high-contrast, monochrome, or a theme file
+        #[clap(long)]
+        theme: Option<String>,
+    },
+    /// Send one message to a group and exit
+    Send {
+        /// Group to post to
+        #[clap(long)]
+        group: String,
+        /// Log in as this user to send; the password is read from VEIL_PASSWORD
+        #[clap(long)]
+        from: Option<String>,
+        /// Message text
+        text: String,
+    },
+    /// Print incoming messages until the connection closes
+    Tail {
+        /// Only print messages for this group
+        #[clap(long)]
+        group: Option<String>,
+        /// Log in as this user to read private groups; the password is read from VEIL_PASSWORD
+        #[clap(long)]
+        from: Option<String>,
+        /// Print one JSON object per line
+        #[clap(long)]
+        json: bool,
+    },
+    /// List registered users
+    Users,
+    /// List groups and their members
+    Groups,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    match &cli.command {
-        Commands::Server => {
+    match cli.command {
+        Commands::Server { config } => {
             println!("Starting Veil Server...");
             #[cfg(feature = "server")]
-            server::run_server().await; // Call run_server from server module
+            {
+                let config = match config {
+                    Some(path) => server::ServerConfig::load(&path)?,
+                    None => server::ServerConfig::default(),
+                };
+                server::run_server(config).await?;
+            }
             #[cfg(not(feature = "server"))]
-            println!("Server feature not enabled. Compile with `--features server`");
+            {
+                let _ = config;
+                println!("Server feature not enabled. Compile with `--features server`");
+            }
+        }
+        #[cfg(feature = "server")]
+        Commands::HashPassword => {
+            let mut password = String::new();
+            std::io::stdin().read_line(&mut password)?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            server::password::validate_password(password)?;
+            println!("{}", server::password::PasswordHash::new(password));
+        }
+        #[cfg(not(feature = "server"))]
+        Commands::HashPassword => {
+            eprintln!("Server feature not enabled. Compile with `--features server`")
         }
-        Commands::Client => {
+        Commands::Client { config, theme } => {
             println!("Starting Veil Client...");
             #[cfg(feature = "client")]
-            client::run_client()?; // Call run_client from client module
+            {
+                // Config errors are reported before the terminal is taken over
+                let config = client::config::Config::load(config.as_deref(), theme.as_deref())?;
+                tokio::task::block_in_place(|| client::run_client(cli.server, config))?;
+                // The TUI loop blocks
+            }
             #[cfg(not(feature = "client"))]
-            println!("Client feature not enabled. Compile with `--features client`");
+            {
+                let _ = (config, theme);
+                println!("Client feature not enabled. Compile with `--features client`");
+            }
+        }
+        #[cfg(feature = "client")]
+        Commands::Send { group, from, text } => {
+            client::headless::send(&cli.server, &group, from, text).await?
+        }
+        #[cfg(feature = "client")]
+        Commands::Tail { group, from, json } => {
+            client::headless::tail(&cli.server, group.as_deref(), from, json).await?
         }
+        #[cfg(feature = "client")]
+        Commands::Users => client::headless::users(&cli.server).await?,
+        #[cfg(feature = "client")]
+        Commands::Groups => client::headless::groups(&cli.server).await?,
+        #[cfg(not(feature = "client"))]
+        _ => eprintln!("Client feature not enabled. Compile with `--features client`"),
     }
     Ok(())
 }
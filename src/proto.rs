@@ -0,0 +1,437 @@
+//! Wire types shared by the server and every client.
+//!
+//! Depends only on `serde`, so third parties can speak the protocol without
+//! pulling in the server or the TUI. With the `server` feature the types also
+//! derive `utoipa::ToSchema` for the OpenAPI document.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Name of the lobby group every server starts with.
+pub const DEFAULT_GROUP: &str = "general";
+
+// --- Versioning ---
+
+/// Version of the HTTP API and WebSocket protocol this build speaks.
+pub const API_VERSION: u32 = 1;
+/// Prefix of every versioned route, e.g. `/v1/users` and `/v1/ws`.
+pub const API_PREFIX: &str = "/v1";
+/// Request header naming the API version a client expects. Servers reject
+/// versions they don't speak and echo the version they answered with.
+pub const VERSION_HEADER: &str = "veil-api-version";
+/// WebSocket subprotocol for [`API_VERSION`].
+pub const WS_SUBPROTOCOL: &str = "veil.v1";
+/// Header carrying the ID the server assigned a request; error bodies repeat
+/// it as `request_id`.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// --- HTTP payloads ---
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct User {
+    pub id: String, // Random and opaque; don't rely on its format
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_hash: Option<String>, // Hex SHA-256 of the avatar image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_text: Option<String>,
+    #[serde(default)]
+    pub role: Role,
+    /// Show this user as offline to everyone else, whatever they're doing.
+    #[serde(default)]
+    pub hide_presence: bool,
+    /// Send no delivery or read receipts for others' messages. Clients
+    /// honour this; the server also drops any that are sent anyway.
+    #[serde(default)]
+    pub hide_receipts: bool,
+}
+
+/// What an account may do beyond its own profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    /// May also delete other users' accounts.
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct CreateUserPayload {
+    pub username: String,
+    pub password: String,
+}
+
+/// Body of `PATCH /users/{id}`. Absent fields are left as they are; an
+/// empty string clears the field.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UpdateUserPayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hide_presence: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hide_receipts: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct LoginPayload {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct LoginResponse {
+    pub token: String, // Pass as `?token=` when opening the WebSocket
+    pub user: User,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Group {
+    pub name: String,
+    pub members: Vec<String>, // Usernames
+    #[serde(default, skip_serializing_if = "Retention::is_unbounded")]
+    pub retention: Retention,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct CreateGroupPayload {
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub retention: Retention,
+}
+
+/// How long a group's stored messages are kept. The server sweeps out
+/// messages past either bound every so often; absent bounds don't apply,
+/// though the server's own per-group cap always does.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Retention {
+    /// Messages older than this many seconds are dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Only the newest this many messages are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_count: Option<usize>,
+}
+
+/// Whether a user can be reached right now.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    /// Has a WebSocket open.
+    Online,
+    /// Has a WebSocket open but said they're away.
+    Away,
+    /// No open WebSocket, or hides their presence.
+    #[default]
+    Offline,
+}
+
+/// Body of `GET /users/{id}/presence`, and of presence frames.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Presence {
+    pub user_id: String,
+    pub username: String,
+    pub status: PresenceStatus,
+    /// When an offline user's last connection closed, in milliseconds since
+    /// the Unix epoch. Absent if the server hasn't seen them since it started,
+    /// or if they hide their presence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+}
+
+/// Body of `PUT /users/{id}/presence`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct SetPresencePayload {
+    pub away: bool,
+}
+
+/// Body of HTTP error responses.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+    pub code: String, // Stable and machine-readable, e.g. "username_taken"
+    pub message: String,
+    /// Quote this when reporting a problem; it matches the server's logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// For `rate_limited`: how long to wait before trying again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+/// Answer to `POST /blobs`: where the upload can be fetched from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct BlobInfo {
+    pub hash: String, // Hex SHA-256 of the contents, as in `GET /blobs/{hash}`
+    pub size: u64,
+}
+
+// --- WebSocket frames ---
+
+/// Close code the server sends on a user's sockets when their account is
+/// deleted. Application codes live in 4000-4999.
+pub const CLOSE_ACCOUNT_DELETED: u16 = 4001;
+
+/// Where a chat message is going: a group, or a single user.
+///
+/// Flattened into [`ChatMessage`], so a group message serializes as
+/// `{"group": "general", ...}` and a direct message as `{"direct": "alice", ...}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Group(String),  // Group name
+    Direct(String), // Recipient username
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Group(name) => write!(f, "#{}", name),
+            Target::Direct(username) => write!(f, "@{}", username),
+        }
+    }
+}
+
+/// A chat message as it travels over the WebSocket.
+///
+/// `id`, `seq`, `sender` and `sent_at` are filled in by the server when it
+/// relays the message; any values set by the client are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ChatMessage {
+    #[serde(flatten)]
+    pub target: Target,
+    /// Unique per message; receipts refer to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Position in the group's stored history, counting from 1. Direct
+    /// messages aren't stored and have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<u64>, // Milliseconds since the Unix epoch
+    pub body: String,
+}
+
+/// Which page of a group's history to read. `before` and `after` are
+/// sequence numbers and exclusive. With `after` the page starts just after
+/// it, for catching up; otherwise it ends just before `before`, or with the
+/// newest message.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "server", into_params(parameter_in = Query))]
+pub struct HistoryQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<u64>,
+    /// Messages per page; 50 unless given, and never more than the server's
+    /// maximum.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Messages from a group's history, oldest first. `more` says whether
+/// further messages lie beyond the page in the direction it was read.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HistoryPage {
+    pub group: String,
+    pub messages: Vec<ChatMessage>,
+    pub more: bool,
+}
+
+/// The WebSocket equivalent of `GET /groups/{name}/messages`, e.g.
+/// `{"history_request": {"group": "general", "before": 120}}`. Answered with a
+/// [`HistoryFrame`], or an error frame.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HistoryRequestFrame {
+    pub history_request: HistoryRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HistoryRequest {
+    pub group: String,
+    #[serde(flatten)]
+    pub query: HistoryQuery,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HistoryFrame {
+    pub history: HistoryPage,
+}
+
+/// Sent by the server when it refuses a frame, e.g.
+/// `{"error": {"code": "rate_limited", "message": "...", "retry_after_ms": 800}}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ErrorFrame {
+    pub error: ErrorBody,
+}
+
+/// Sent by the server when a user who shares a group with the recipient
+/// comes online, goes away or goes offline, and for everyone already online
+/// when the recipient connects.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct PresenceFrame {
+    pub presence: Presence,
+}
+
+/// Sent by a client while its user types in a conversation, at most every
+/// few seconds; relayed to the others who can read it and never stored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct TypingFrame {
+    pub typing: Typing,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Typing {
+    #[serde(flatten)]
+    pub target: Target,
+    /// Filled in by the server, like a message's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+}
+
+/// A receipt for messages from `to`, relayed to that user only.
+///
+/// The server passes `payload` on without looking inside. Clients put a plain
+/// JSON [`ReceiptBody`] there for now, so the server can read receipts; they
+/// are not end-to-end encrypted yet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ReceiptFrame {
+    pub receipt: Receipt,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Receipt {
+    pub to: String, // Username
+    /// Filled in by the server, like a message's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    pub payload: String,
+}
+
+/// What a receipt's payload says: how far some messages got.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptBody {
+    pub status: ReceiptStatus,
+    pub message_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptStatus {
+    /// Reached one of the recipient's clients.
+    Delivered,
+    /// Shown to the recipient.
+    Read,
+}
+
+/// What a message's body says. Plain text unless the sender set a timer or
+/// attached a file, in which case it is a JSON object such as
+/// `{"text": "gone soon", "expires_after_secs": 30}`. Recipients delete the
+/// message that long after it was sent. The server doesn't act on any of it,
+/// but bodies aren't encrypted yet, so it can read them, timers included.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MessagePayload {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_after_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+}
+
+/// A file sent with a message. The file is encrypted under a key of its own
+/// and uploaded as a blob, and the message carries the key. Message bodies
+/// aren't encrypted yet, so the server, which relays and stores them, can
+/// read attachments too.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub name: String, // File name, without directories
+    pub size: u64,    // Bytes before encryption
+    pub hash: String, // Hex SHA-256 of the encrypted blob, which is its address
+    pub key: String,  // Hex ChaCha20-Poly1305 key
+}
+
+impl Retention {
+    pub fn is_unbounded(&self) -> bool {
+        self.max_age_secs.is_none() && self.max_count.is_none()
+    }
+}
+
+impl MessagePayload {
+    /// Whether the payload travels as the bare text.
+    pub fn is_plain(&self) -> bool {
+        self.expires_after_secs.is_none() && self.attachment.is_none()
+    }
+}
+
+impl ChatMessage {
+    /// One-line rendering used by the TUI history and `veil tail`.
+    pub fn display_line(&self) -> String {
+        format!(
+            "[{}] {}: {}",
+            self.target,
+            self.sender.as_deref().unwrap_or("anonymous"),
+            self.body
+        )
+    }
+
+    /// The conversation this message belongs to from `me`'s point of view.
+    ///
+    /// A direct message lives under the other party, whichever side sent it.
+    pub fn conversation_for(&self, me: Option<&str>) -> Target {
+        conversation_for(&self.target, self.sender.as_deref(), me)
+    }
+}
+
+impl Typing {
+    /// The conversation being typed in, from `me`'s point of view.
+    pub fn conversation_for(&self, me: Option<&str>) -> Target {
+        conversation_for(&self.target, self.sender.as_deref(), me)
+    }
+}
+
+fn conversation_for(target: &Target, sender: Option<&str>, me: Option<&str>) -> Target {
+    match (target, sender) {
+        (Target::Direct(_), Some(sender)) if me != Some(sender) => {
+            Target::Direct(sender.to_string())
+        }
+        (target, _) => target.clone(),
+    }
+}
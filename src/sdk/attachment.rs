@@ -0,0 +1,62 @@
+use crate::hex;
+use crate::proto::Attachment;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+
+// --- Attachment encryption ---
+//
+// Every file gets a fresh random ChaCha20-Poly1305 key and is sealed in one
+// piece. A key never encrypts anything else, so the all-zero nonce is safe.
+// The blob's address is the SHA-256 of the ciphertext, which lets recipients
+// check a download before decrypting it. The key goes in the message body,
+// which isn't encrypted yet, so this keeps the file from anyone without the
+// message but not from the server.
+
+const KEY_LEN: usize = 32;
+
+/// Encrypt `data` under a new key. Returns the attachment to put in a
+/// message, once the blob is uploaded, and the blob to upload.
+pub fn seal(name: &str, data: &[u8]) -> Result<(Attachment, Vec<u8>), String> {
+    let mut key = [0u8; KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| "no randomness for a key".to_string())?;
+    let mut blob = data.to_vec();
+    cipher(&key)?
+        .seal_in_place_append_tag(nonce(), Aad::empty(), &mut blob)
+        .map_err(|_| "encryption failed".to_string())?;
+    let attachment = Attachment {
+        name: name.to_string(),
+        size: data.len() as u64,
+        hash: hex::encode(digest(&SHA256, &blob).as_ref()),
+        key: hex::encode(&key),
+    };
+    Ok((attachment, blob))
+}
+
+/// Check a downloaded blob against `attachment` and decrypt it.
+pub fn open(attachment: &Attachment, mut blob: Vec<u8>) -> Result<Vec<u8>, String> {
+    if hex::encode(digest(&SHA256, &blob).as_ref()) != attachment.hash {
+        return Err("download does not match its hash".to_string());
+    }
+    let key = hex::decode(&attachment.key)
+        .filter(|key| key.len() == KEY_LEN)
+        .ok_or_else(|| "malformed key".to_string())?;
+    let len = cipher(&key)?
+        .open_in_place(nonce(), Aad::empty(), &mut blob)
+        .map_err(|_| "wrong key or corrupted file".to_string())?
+        .len();
+    blob.truncate(len);
+    Ok(blob)
+}
+
+fn cipher(key: &[u8]) -> Result<LessSafeKey, String> {
+    UnboundKey::new(&CHACHA20_POLY1305, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| "malformed key".to_string())
+}
+
+fn nonce() -> Nonce {
+    Nonce::assume_unique_for_key([0; NONCE_LEN])
+}
@@ -0,0 +1,100 @@
+use reqwest::StatusCode;
+use std::fmt;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::error::Error as WsError;
+
+/// Everything that can go wrong while talking to a Veil server.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The HTTP request could not be sent or its body could not be read.
+    Http(reqwest::Error),
+    /// The WebSocket handshake or a frame send/receive failed.
+    WebSocket(WsError),
+    /// The server answered with a non-success status. `code` is the
+    /// machine-readable error code from the body, or empty if there was none;
+    /// `request_id` identifies the request in the server's logs.
+    Api {
+        status: StatusCode,
+        code: String,
+        message: String,
+        request_id: Option<String>,
+    },
+    /// A frame or response body did not match the expected shape.
+    Decode(serde_json::Error),
+    /// Nothing arrived on the WebSocket for this long, not even a pong; the
+    /// connection is presumed dead.
+    Timeout(Duration),
+    /// `send` was called before `connect`.
+    NotConnected,
+    /// The call needs a session from `login`.
+    NotLoggedIn,
+    /// An attachment could not be encrypted, or a download didn't match
+    /// its attachment.
+    Attachment(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "HTTP error: {}", e),
+            ClientError::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            ClientError::Api {
+                status,
+                message,
+                request_id,
+                ..
+            } => {
+                write!(f, "server returned {}", status)?;
+                if !message.is_empty() {
+                    write!(f, ": {}", message)?;
+                }
+                match request_id {
+                    Some(id) => write!(f, " (request {})", id),
+                    None => Ok(()),
+                }
+            }
+            ClientError::Decode(e) => write!(f, "malformed server data: {}", e),
+            ClientError::Timeout(silence) => write!(
+                f,
+                "server stopped responding (silent for {}s)",
+                silence.as_secs()
+            ),
+            ClientError::NotConnected => write!(f, "not connected to the WebSocket"),
+            ClientError::NotLoggedIn => write!(f, "not logged in"),
+            ClientError::Attachment(problem) => write!(f, "attachment error: {}", problem),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(e) => Some(e),
+            ClientError::WebSocket(e) => Some(e),
+            ClientError::Decode(e) => Some(e),
+            ClientError::Api { .. }
+            | ClientError::Timeout(_)
+            | ClientError::NotConnected
+            | ClientError::NotLoggedIn
+            | ClientError::Attachment(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+impl From<WsError> for ClientError {
+    fn from(e: WsError) -> Self {
+        ClientError::WebSocket(e)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Decode(e)
+    }
+}
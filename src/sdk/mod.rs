@@ -0,0 +1,574 @@
+//! Async client for talking to a Veil server without the TUI.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), project_veil::sdk::ClientError> {
+//! use futures::StreamExt;
+//! use project_veil::sdk::{Event, VeilClient};
+//!
+//! let mut client = VeilClient::new("http://localhost:3000");
+//! client.login("alice", "correct horse").await?;
+//! let mut events = client.connect().await?;
+//! client.send("general", "hello").await?;
+//! while let Some(event) = events.next().await {
+//!     if let Event::Message(msg) = event? {
+//!         println!("{}", msg.display_line());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod attachment;
+mod error;
+mod types;
+
+pub use self::error::ClientError;
+pub use self::types::{Event, Heartbeat};
+pub use crate::proto::{
+    Attachment, BlobInfo, ChatMessage, ErrorBody, Group, HistoryPage, HistoryQuery, LoginResponse,
+    MessagePayload, Presence, PresenceStatus, ReceiptBody, ReceiptStatus, Retention, Role, Target,
+    Typing, UpdateUserPayload, User,
+};
+
+use crate::proto::{
+    ErrorFrame, HistoryFrame, HistoryRequest, HistoryRequestFrame, PresenceFrame, Receipt,
+    ReceiptFrame, SetPresencePayload, TypingFrame, API_PREFIX, API_VERSION, REQUEST_ID_HEADER,
+    VERSION_HEADER, WS_SUBPROTOCOL,
+};
+use futures::sink::SinkExt;
+use futures::stream::{self, BoxStream, SplitSink, StreamExt};
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Client, Method, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+pub type Result<T, E = ClientError> = std::result::Result<T, E>;
+
+/// Stream of events from the server; ends when the connection closes.
+pub type EventStream = BoxStream<'static, Result<Event>>;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Uploads are streamed in pieces this big, so progress can be reported.
+const TRANSFER_CHUNK: usize = 64 * 1024;
+type WsSink = SplitSink<WsStream, Message>;
+
+/// Connection to one Veil server.
+///
+/// Cloning is cheap and clones share the session and the WebSocket, so one
+/// task can consume events while others send.
+#[derive(Clone)]
+pub struct VeilClient {
+    http: Client,
+    server_url: String,
+    session: Option<LoginResponse>,
+    ws_tx: Option<Arc<Mutex<WsSink>>>,
+    heartbeat: Option<Heartbeat>,
+}
+
+impl VeilClient {
+    /// `server_url` is the HTTP base URL, e.g. `http://localhost:3000`.
+    pub fn new(server_url: impl Into<String>) -> Self {
+        VeilClient {
+            http: Client::new(),
+            server_url: server_url.into().trim_end_matches('/').to_string(),
+            session: None,
+            ws_tx: None,
+            heartbeat: Some(Heartbeat::default()),
+        }
+    }
+
+    /// Change the heartbeat for connections opened afterwards; `None` turns
+    /// it off, so a dead connection goes unnoticed until the OS gives up.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+    }
+
+    pub fn server_url(&self) -> &str {
+        &self.server_url
+    }
+
+    /// The user this client is logged in as, if any.
+    pub fn user(&self) -> Option<&User> {
+        self.session.as_ref().map(|s| &s.user)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.ws_tx.is_some()
+    }
+
+    // --- HTTP API ---
+
+    /// Start a session for an existing user. Messages sent over a connection
+    /// opened afterwards are attributed to this user.
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<User> {
+        let response = self
+            .request(Method::POST, "/login")
+            .json(&json!({ "username": username, "password": password }))
+            .send()
+            .await?;
+        let session: LoginResponse = decode(response).await?;
+        let user = session.user.clone();
+        self.session = Some(session);
+        Ok(user)
+    }
+
+    /// Pick up a session from an earlier `login`, e.g. one kept between
+    /// runs, instead of starting a new one.
+    pub fn resume(&mut self, session: LoginResponse) {
+        self.session = Some(session);
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>> {
+        decode(self.request(Method::GET, "/users").send().await?).await
+    }
+
+    /// Register a new account; log in with the same password afterwards.
+    pub async fn create_user(&self, username: &str, password: &str) -> Result<User> {
+        let response = self
+            .request(Method::POST, "/users")
+            .json(&json!({ "username": username, "password": password }))
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// Delete an account; the session must belong to it or to an admin.
+    pub async fn delete_user(&self, user_id: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, &format!("/users/{}", user_id))
+            .send()
+            .await?;
+        check(response).await.map(drop)
+    }
+
+    /// Change profile fields of the logged-in user; see [`UpdateUserPayload`].
+    pub async fn update_profile(&mut self, update: &UpdateUserPayload) -> Result<User> {
+        let id = &self.user().ok_or(ClientError::NotLoggedIn)?.id;
+        let response = self
+            .request(Method::PATCH, &format!("/users/{}", id))
+            .json(update)
+            .send()
+            .await?;
+        let user: User = decode(response).await?;
+        if let Some(session) = &mut self.session {
+            session.user = user.clone();
+        }
+        Ok(user)
+    }
+
+    /// `user_id`'s presence as this session may see it.
+    pub async fn presence(&self, user_id: &str) -> Result<Presence> {
+        let path = format!("/users/{}/presence", user_id);
+        decode(self.request(Method::GET, &path).send().await?).await
+    }
+
+    /// Mark the logged-in user away, or back. Away lasts until cleared or
+    /// until the user's last connection closes.
+    pub async fn set_away(&self, away: bool) -> Result<Presence> {
+        let id = &self.user().ok_or(ClientError::NotLoggedIn)?.id;
+        let response = self
+            .request(Method::PUT, &format!("/users/{}/presence", id))
+            .json(&SetPresencePayload { away })
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    pub async fn list_groups(&self) -> Result<Vec<Group>> {
+        decode(self.request(Method::GET, "/groups").send().await?).await
+    }
+
+    pub async fn create_group(&self, name: &str, members: &[String]) -> Result<Group> {
+        let response = self
+            .request(Method::POST, "/groups")
+            .json(&json!({ "name": name, "members": members }))
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// Change how long `group` keeps its stored messages; requires a
+    /// logged-in member, or an admin for open groups.
+    pub async fn set_retention(&self, group: &str, retention: &Retention) -> Result<Group> {
+        let path = format!("/groups/{}/retention", encode_segment(group));
+        decode(
+            self.request(Method::PUT, &path)
+                .json(retention)
+                .send()
+                .await?,
+        )
+        .await
+    }
+
+    /// Upload a blob, calling `progress` with the bytes sent so far and the
+    /// total as it goes; requires a logged-in session.
+    pub async fn upload_blob(
+        &self,
+        data: Vec<u8>,
+        mut progress: impl FnMut(u64, u64) + Send + 'static,
+    ) -> Result<BlobInfo> {
+        let total = data.len() as u64;
+        let chunks: Vec<Vec<u8>> = data.chunks(TRANSFER_CHUNK).map(<[u8]>::to_vec).collect();
+        let mut sent = 0;
+        let body = stream::iter(chunks).map(move |chunk| {
+            sent += chunk.len() as u64;
+            progress(sent, total);
+            Ok::<_, std::io::Error>(chunk)
+        });
+        let response = self
+            .request(Method::POST, "/blobs")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_LENGTH, total)
+            .body(reqwest::Body::wrap_stream(body))
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// Download blob `hash`, calling `progress` with the bytes received so
+    /// far and the total, or 0 if the server didn't say.
+    pub async fn download_blob(
+        &self,
+        hash: &str,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<Vec<u8>> {
+        let path = format!("/blobs/{}", encode_segment(hash));
+        let mut response = check(self.request(Method::GET, &path).send().await?).await?;
+        let total = response.content_length().unwrap_or_default();
+        let mut data = Vec::with_capacity(total as usize);
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+            progress(data.len() as u64, total);
+        }
+        Ok(data)
+    }
+
+    /// Encrypt `data` under a new key and upload it. Send the returned
+    /// attachment in a message's [`MessagePayload`] to share the file; the
+    /// key travels there in plaintext, so the server can read the file.
+    pub async fn upload_attachment(
+        &self,
+        name: &str,
+        data: &[u8],
+        progress: impl FnMut(u64, u64) + Send + 'static,
+    ) -> Result<Attachment> {
+        let (attachment, blob) = attachment::seal(name, data).map_err(ClientError::Attachment)?;
+        let stored = self.upload_blob(blob, progress).await?;
+        if stored.hash != attachment.hash {
+            return Err(ClientError::Attachment(
+                "server stored the file under another hash".to_string(),
+            ));
+        }
+        Ok(attachment)
+    }
+
+    /// Download an attachment from a message and decrypt it.
+    pub async fn download_attachment(
+        &self,
+        attachment: &Attachment,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<Vec<u8>> {
+        let blob = self.download_blob(&attachment.hash, progress).await?;
+        attachment::open(attachment, blob).map_err(ClientError::Attachment)
+    }
+
+    // --- WebSocket ---
+
+    /// A page of `group`'s stored messages; see [`HistoryQuery`] for which.
+    pub async fn history(&self, group: &str, query: &HistoryQuery) -> Result<HistoryPage> {
+        let path = format!("/groups/{}/messages", encode_segment(group));
+        decode(self.request(Method::GET, &path).query(query).send().await?).await
+    }
+
+    /// Open the WebSocket and return the stream of incoming events.
+    ///
+    /// Replaces any previous connection.
+    pub async fn connect(&mut self) -> Result<EventStream> {
+        let mut url = self.ws_url();
+        if let Some(session) = &self.session {
+            url = format!("{}?token={}", url, session.token);
+        }
+        // Ask for our protocol version; the handshake fails unless the server
+        // agrees to it
+        let mut request = url.into_client_request()?;
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(WS_SUBPROTOCOL),
+        );
+        let (ws_stream, _response) = connect_async(request).await?;
+        tracing::info!("WebSocket handshake has been successfully completed");
+
+        let (ws_tx, ws_rx) = ws_stream.split();
+        let ws_tx = Arc::new(Mutex::new(ws_tx));
+        if let Some(heartbeat) = self.heartbeat {
+            tokio::spawn(ping(Arc::downgrade(&ws_tx), heartbeat.interval));
+        }
+        self.ws_tx = Some(ws_tx);
+
+        // The server's own pings are answered by tungstenite
+        let timeout = self.heartbeat.map(|h| h.timeout);
+        let events = stream::unfold(Some(ws_rx), move |ws_rx| async move {
+            let mut ws_rx = ws_rx?;
+            loop {
+                let frame = match timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, ws_rx.next()).await {
+                        Ok(frame) => frame?,
+                        Err(_) => return Some((Err(ClientError::Timeout(timeout)), None)),
+                    },
+                    None => ws_rx.next().await?,
+                };
+                let event = match frame {
+                    Ok(Message::Text(text)) => Ok(parse_frame(text.as_str())),
+                    Ok(Message::Close(Some(frame))) => Ok(Event::Closed {
+                        code: frame.code.into(),
+                        reason: frame.reason.to_string(),
+                    }),
+                    Ok(Message::Pong(payload)) => match latency(&payload) {
+                        Some(rtt) => Ok(Event::Latency(rtt)),
+                        None => continue,
+                    },
+                    Ok(_) => continue,
+                    Err(e) => Err(e.into()),
+                };
+                return Some((event, Some(ws_rx)));
+            }
+        });
+        Ok(events.boxed())
+    }
+
+    /// Post `body` to `group` over the open WebSocket.
+    pub async fn send(&self, group: &str, body: &str) -> Result<()> {
+        self.send_to(&Target::Group(group.to_string()), body).await
+    }
+
+    /// Send `body` to a single user; requires a logged-in session.
+    pub async fn send_direct(&self, username: &str, body: &str) -> Result<()> {
+        self.send_to(&Target::Direct(username.to_string()), body)
+            .await
+    }
+
+    pub async fn send_to(&self, target: &Target, body: &str) -> Result<()> {
+        self.send_frame(&ChatMessage {
+            target: target.clone(),
+            id: None,
+            seq: None,
+            sender: None,
+            sent_at: None,
+            body: body.to_string(),
+        })
+        .await
+    }
+
+    /// Ask for a page of `group`'s history over the WebSocket; it arrives as
+    /// [`Event::History`].
+    pub async fn request_history(&self, group: &str, query: &HistoryQuery) -> Result<()> {
+        let history_request = HistoryRequest {
+            group: group.to_string(),
+            query: query.clone(),
+        };
+        self.send_frame(&HistoryRequestFrame { history_request })
+            .await
+    }
+
+    /// Tell the others in `target` that we are typing. The server drops
+    /// these when sent more than every few seconds.
+    pub async fn send_typing(&self, target: &Target) -> Result<()> {
+        let typing = Typing {
+            target: target.clone(),
+            sender: None,
+        };
+        self.send_frame(&TypingFrame { typing }).await
+    }
+
+    /// Tell `username` how far messages of theirs got; requires a logged-in
+    /// session. The body travels as plain JSON, readable by the server.
+    pub async fn send_receipt(&self, username: &str, body: &ReceiptBody) -> Result<()> {
+        let receipt = Receipt {
+            to: username.to_string(),
+            sender: None,
+            payload: serde_json::to_string(body)?,
+        };
+        self.send_frame(&ReceiptFrame { receipt }).await
+    }
+
+    async fn send_frame<T: Serialize>(&self, frame: &T) -> Result<()> {
+        let ws_tx = self.ws_tx.as_ref().ok_or(ClientError::NotConnected)?;
+        let text = serde_json::to_string(frame)?;
+        ws_tx.lock().await.send(Message::Text(text.into())).await?;
+        Ok(())
+    }
+
+    /// Close the WebSocket; the event stream ends once the server confirms.
+    pub async fn disconnect(&mut self) -> Result<()> {
+        if let Some(ws_tx) = self.ws_tx.take() {
+            ws_tx.lock().await.close().await?;
+        }
+        Ok(())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}{}", self.server_url, API_PREFIX, path)
+    }
+
+    /// A request to API `path`, authenticated with the session token if
+    /// logged in.
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, self.url(path))
+            .header(VERSION_HEADER, API_VERSION);
+        match &self.session {
+            Some(session) => request.bearer_auth(&session.token),
+            None => request,
+        }
+    }
+
+    /// Derive the WebSocket endpoint from the HTTP base URL of the server.
+    fn ws_url(&self) -> String {
+        let base = &self.server_url;
+        if let Some(rest) = base.strip_prefix("https://") {
+            format!("wss://{}{}/ws", rest, API_PREFIX)
+        } else if let Some(rest) = base.strip_prefix("http://") {
+            format!("ws://{}{}/ws", rest, API_PREFIX)
+        } else {
+            format!("{}{}/ws", base, API_PREFIX)
+        }
+    }
+}
+
+// Ping until the connection goes away. The payload is the send time, so the
+// pong tells us the round trip without keeping any state here.
+async fn ping(ws_tx: Weak<Mutex<WsSink>>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let Some(ws_tx) = ws_tx.upgrade() else {
+            return; // Disconnected
+        };
+        let payload = now_micros().to_be_bytes().to_vec();
+        if ws_tx
+            .lock()
+            .await
+            .send(Message::Ping(payload.into()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Round-trip time for a pong to one of our pings, or `None` for pongs we
+/// didn't ask for.
+fn latency(payload: &[u8]) -> Option<Duration> {
+    let sent = u64::from_be_bytes(payload.try_into().ok()?);
+    now_micros().checked_sub(sent).map(Duration::from_micros)
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64)
+}
+
+fn parse_frame(text: &str) -> Event {
+    if let Ok(msg) = serde_json::from_str(text) {
+        Event::Message(msg)
+    } else if let Ok(ErrorFrame { error }) = serde_json::from_str(text) {
+        Event::Error(error)
+    } else if let Ok(PresenceFrame { presence }) = serde_json::from_str(text) {
+        Event::Presence(presence)
+    } else if let Ok(HistoryFrame { history }) = serde_json::from_str(text) {
+        Event::History(history)
+    } else if let Ok(TypingFrame { typing }) = serde_json::from_str(text) {
+        Event::Typing(typing)
+    } else if let Some((sender, body)) = parse_receipt(text) {
+        Event::Receipt { sender, body }
+    } else {
+        Event::Unknown(text.to_string())
+    }
+}
+
+/// The body to send for `payload`: the bare text unless it carries more.
+pub fn encode_payload(payload: &MessagePayload) -> String {
+    if payload.is_plain() {
+        payload.text.clone()
+    } else {
+        serde_json::to_string(payload).unwrap_or_else(|_| payload.text.clone())
+    }
+}
+
+/// Read a received body back into its payload. Anything that isn't a
+/// payload object with more than text, including text that merely looks
+/// like JSON, is plain text.
+pub fn decode_payload(body: &str) -> MessagePayload {
+    serde_json::from_str::<MessagePayload>(body)
+        .ok()
+        .filter(|payload| !payload.is_plain())
+        .unwrap_or_else(|| MessagePayload {
+            text: body.to_string(),
+            ..Default::default()
+        })
+}
+
+fn parse_receipt(text: &str) -> Option<(String, ReceiptBody)> {
+    let ReceiptFrame { receipt } = serde_json::from_str(text).ok()?;
+    Some((
+        receipt.sender?,
+        serde_json::from_str(&receipt.payload).ok()?,
+    ))
+}
+
+/// Turn non-success responses into `ClientError::Api`.
+async fn check(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        // Proxies in front of the server may answer without a JSON body, but
+        // keep the request ID header
+        let header_id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let text = response.text().await.unwrap_or_default();
+        let (code, message, request_id) = match serde_json::from_str::<ErrorBody>(&text) {
+            Ok(body) => (body.code, body.message, body.request_id.or(header_id)),
+            Err(_) => (String::new(), text, header_id),
+        };
+        Err(ClientError::Api {
+            status,
+            code,
+            message,
+            request_id,
+        })
+    }
+}
+
+/// Percent-encode a path segment; group names may contain spaces, `/` and
+/// other characters with meaning in URLs.
+fn encode_segment(segment: &str) -> String {
+    let mut encoded = String::new();
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let bytes = check(response).await?.bytes().await?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
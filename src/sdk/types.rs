@@ -0,0 +1,53 @@
+use crate::proto::{ChatMessage, ErrorBody, HistoryPage, Presence, ReceiptBody, Typing};
+use std::time::Duration;
+
+/// Something that arrived on the WebSocket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Message(ChatMessage),
+    /// The server refused a frame we sent, e.g. with code `rate_limited`.
+    Error(ErrorBody),
+    /// Someone we share a group with came online, went away or went
+    /// offline; also sent for everyone already online when we connect.
+    Presence(Presence),
+    /// The answer to [`request_history`](super::VeilClient::request_history).
+    History(HistoryPage),
+    /// Someone is typing in a conversation we can read.
+    Typing(Typing),
+    /// `sender` got or read messages we sent.
+    Receipt {
+        sender: String,
+        body: ReceiptBody,
+    },
+    /// The server answered one of our heartbeat pings after this long.
+    Latency(Duration),
+    /// A text frame this client version does not understand.
+    Unknown(String),
+    /// The server closed the connection, e.g. with
+    /// [`CLOSE_ACCOUNT_DELETED`](crate::proto::CLOSE_ACCOUNT_DELETED).
+    /// The stream ends after this.
+    Closed {
+        code: u16,
+        reason: String,
+    },
+}
+
+/// How the client keeps an eye on an open WebSocket.
+///
+/// Every `interval` it pings the server, which answers with a pong that is
+/// reported as [`Event::Latency`]. If nothing at all arrives for `timeout`,
+/// the event stream ends with [`ClientError::Timeout`](super::ClientError::Timeout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(45),
+        }
+    }
+}
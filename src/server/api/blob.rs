@@ -0,0 +1,126 @@
+use crate::proto::{BlobInfo, ErrorBody};
+use crate::server::api::{ApiError, AuthUser};
+use crate::server::blobs::{blob_hash, is_blob_hash};
+use crate::server::state::AppState;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Json, Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+};
+use std::io;
+
+// --- Blob Handlers ---
+
+// Store an attachment. Blobs are opaque to the server and addressed by the
+// SHA-256 of their contents; clients encrypt them first.
+#[utoipa::path(
+    post,
+    path = "/blobs",
+    tag = "blobs",
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Blob stored", body = BlobInfo),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 413, description = "Blob too large, or over the uploader's quota", body = ErrorBody),
+        (status = 422, description = "Empty blob", body = ErrorBody),
+    )
+)]
+pub async fn upload_blob(
+    State(state): State<AppState>,
+    AuthUser(caller): AuthUser,
+    body: Body,
+) -> Result<(StatusCode, Json<BlobInfo>), ApiError> {
+    let max = state.config.blobs.max_blob_bytes;
+    let bytes = to_bytes(body, max).await.map_err(|_| {
+        let message = format!("blobs must be at most {} bytes", max);
+        ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "blob_too_large", message)
+    })?;
+    if bytes.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "empty_blob",
+            "blobs must not be empty",
+        ));
+    }
+    let hash = blob_hash(&bytes);
+    let size = bytes.len() as u64;
+    let quota = state.config.blobs.quota_bytes_per_user;
+    let (charged, path) = {
+        let mut blobs = state.blobs.lock()?;
+        let charged = blobs
+            .charge(&caller.id, &hash, size, quota)
+            .map_err(|used| {
+                let message = format!(
+                    "uploading {} bytes would exceed your quota ({} of {} bytes used)",
+                    size, used, quota
+                );
+                ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "quota_exceeded", message)
+            })?;
+        (charged, blobs.path(&hash))
+    };
+    if let Err(e) = store(&path, &bytes).await {
+        if charged {
+            state.blobs.lock()?.refund(&caller.id, &hash, size);
+        }
+        return Err(ApiError::internal(e));
+    }
+    tracing::info!(
+        "{} uploaded blob {} ({} bytes)",
+        caller.username,
+        hash,
+        size
+    );
+    Ok((StatusCode::CREATED, Json(BlobInfo { hash, size })))
+}
+
+// Fetch a blob by hash. Anyone may: without the key carried in a message,
+// the contents are unreadable to them, though not to the server.
+#[utoipa::path(
+    get,
+    path = "/blobs/{hash}",
+    tag = "blobs",
+    params(("hash" = String, Path, description = "Hex SHA-256 of the blob")),
+    responses(
+        (status = 200, description = "The blob", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 404, description = "No such blob", body = ErrorBody),
+        (status = 422, description = "Not a hex SHA-256", body = ErrorBody),
+    )
+)]
+pub async fn download_blob(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !is_blob_hash(&hash) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_hash",
+            "blob hashes are 64 lowercase hex digits",
+        ));
+    }
+    let path = state.blobs.lock()?.path(&hash);
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => Ok(([(CONTENT_TYPE, "application/octet-stream")], bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "blob_not_found",
+            "no such blob",
+        )),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+// Write under a temporary name first, so a blob's file is either complete or
+// missing. A file that already exists has the same contents.
+async fn store(path: &std::path::Path, bytes: &[u8]) -> io::Result<()> {
+    if tokio::fs::try_exists(path).await? {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let partial = path.with_extension(format!("{}.part", uuid::Uuid::new_v4().simple()));
+    tokio::fs::write(&partial, bytes).await?;
+    tokio::fs::rename(&partial, path).await
+}
@@ -0,0 +1,129 @@
+use crate::proto::ErrorBody;
+use crate::server::request_id;
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts, Json, Query, Request,
+    },
+    http::{header::RETRY_AFTER, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+/// A failed API request, sent as a JSON [`ErrorBody`] tagged with the ID of
+/// the request it answers.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str, // Stable and machine-readable, e.g. "username_taken"
+    pub message: String,
+    pub retry_after: Option<Duration>, // Sent as `Retry-After` and `retry_after_ms`
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// Refused by a rate limit; try again after `wait`.
+    pub fn rate_limited(wait: Duration) -> Self {
+        ApiError {
+            retry_after: Some(wait),
+            ..ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                format!("too many requests; try again in {:.1}s", wait.as_secs_f64()),
+            )
+        }
+    }
+
+    /// A server-side fault. The details go to the log, not to the client.
+    pub fn internal(details: impl std::fmt::Display) -> Self {
+        tracing::error!("Internal error: {}", details);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "internal server error",
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code.to_string(),
+            message: self.message,
+            request_id: request_id::current(),
+            retry_after_ms: self.retry_after.map(|wait| wait.as_millis() as u64),
+        };
+        let mut response = (self.status, Json(body)).into_response();
+        if let Some(wait) = self.retry_after {
+            // Whole seconds, rounded up so clients don't retry too early
+            let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+// A handler panicked while holding a state lock; fail this request instead
+// of panicking again
+impl<T> From<PoisonError<T>> for ApiError {
+    fn from(e: PoisonError<T>) -> Self {
+        ApiError::internal(e)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_body", rejection.body_text())
+    }
+}
+
+/// A JSON request body whose rejections are [`ApiError`]s, unlike those of
+/// `axum::Json`, which are plain text.
+pub struct ApiJson<T>(pub T);
+
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, ApiError> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Ok(ApiJson(value))
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+
+/// A query string whose rejections are [`ApiError`]s, like [`ApiJson`].
+pub struct ApiQuery<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for ApiQuery<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(ApiQuery(value))
+    }
+}
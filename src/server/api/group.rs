@@ -0,0 +1,254 @@
+use crate::proto::{
+    CreateGroupPayload, ErrorBody, Group, HistoryPage, HistoryQuery, Retention, Role,
+};
+use crate::server::api::user::validate_username;
+use crate::server::api::{ApiError, ApiJson, ApiQuery, AuthUser};
+use crate::server::history::DEFAULT_PAGE_SIZE;
+use crate::server::state::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+
+// --- Group policy ---
+
+pub const GROUP_NAME_MAX_LEN: usize = 64;
+
+/// Group names are 1-64 characters without control characters.
+pub fn validate_group_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().count() > GROUP_NAME_MAX_LEN {
+        return Err(format!(
+            "group name must be 1 to {} characters",
+            GROUP_NAME_MAX_LEN
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("group name must not contain control characters".to_string());
+    }
+    Ok(())
+}
+
+/// Retention bounds must leave something to keep; leave a bound out rather
+/// than set it to 0.
+pub fn validate_retention(retention: &Retention) -> Result<(), ApiError> {
+    if retention.max_age_secs == Some(0) || retention.max_count == Some(0) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_retention",
+            "max_age_secs and max_count must be at least 1",
+        ));
+    }
+    Ok(())
+}
+
+/// Members must be registered users. Names are stored as registered, so a
+/// later account can't inherit a place someone else was given, and each
+/// member is listed once.
+fn registered_members(state: &AppState, names: &[String]) -> Result<Vec<String>, ApiError> {
+    let user_state = state.user_state.lock()?;
+    let mut members: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        if let Err(message) = validate_username(name) {
+            let message = format!("member '{}': {}", name, message);
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_member",
+                message,
+            ));
+        }
+        let Some(user) = user_state.find_by_username(name) else {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "unknown_member",
+                format!("no user named '{}'", name),
+            ));
+        };
+        if !members.contains(&user.username) {
+            members.push(user.username.clone());
+        }
+    }
+    Ok(members)
+}
+
+// --- Group Handlers ---
+
+// Create a new group
+#[utoipa::path(
+    post,
+    path = "/groups",
+    tag = "groups",
+    request_body = CreateGroupPayload,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Group created", body = Group),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 409, description = "Group exists", body = ErrorBody),
+        (status = 422, description = "Invalid group name, member or retention", body = ErrorBody),
+    )
+)]
+pub async fn create_group(
+    State(state): State<AppState>,
+    _caller: AuthUser,
+    ApiJson(payload): ApiJson<CreateGroupPayload>,
+) -> Result<(StatusCode, Json<Group>), ApiError> {
+    if let Err(message) = validate_group_name(&payload.name) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_group_name",
+            message,
+        ));
+    }
+    validate_retention(&payload.retention)?;
+    let members = registered_members(&state, &payload.members)?;
+    let mut group_state = state.group_state.lock()?;
+    if group_state.groups.contains_key(&payload.name) {
+        let message = format!("group '{}' already exists", payload.name);
+        return Err(ApiError::new(StatusCode::CONFLICT, "group_exists", message));
+    }
+
+    let new_group = Group {
+        name: payload.name,
+        members,
+        retention: payload.retention,
+    };
+    group_state
+        .groups
+        .insert(new_group.name.clone(), new_group.clone());
+
+    Ok((StatusCode::CREATED, Json(new_group)))
+}
+
+// List all groups, ordered by name
+#[utoipa::path(
+    get,
+    path = "/groups",
+    tag = "groups",
+    responses((status = 200, description = "All groups, by name", body = [Group]))
+)]
+pub async fn list_groups(State(state): State<AppState>) -> Result<Json<Vec<Group>>, ApiError> {
+    let group_state = state.group_state.lock()?;
+    let mut groups: Vec<Group> = group_state.groups.values().cloned().collect();
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(groups))
+}
+
+// Change how long a group's stored messages are kept. Members of a private
+// group may; open groups belong to everyone, so only admins may there.
+#[utoipa::path(
+    put,
+    path = "/groups/{name}/retention",
+    tag = "groups",
+    params(("name" = String, Path, description = "Group name")),
+    request_body = Retention,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Updated group", body = Group),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Neither a member nor, for open groups, an admin", body = ErrorBody),
+        (status = 404, description = "No such group", body = ErrorBody),
+        (status = 422, description = "Invalid retention", body = ErrorBody),
+    )
+)]
+pub async fn set_retention(
+    State(state): State<AppState>,
+    AuthUser(caller): AuthUser,
+    Path(name): Path<String>,
+    ApiJson(retention): ApiJson<Retention>,
+) -> Result<Json<Group>, ApiError> {
+    validate_retention(&retention)?;
+    let mut group_state = state.group_state.lock()?;
+    let group = group_state
+        .groups
+        .get_mut(&name)
+        .ok_or_else(|| group_not_found(&name))?;
+    let allowed = if group.members.is_empty() {
+        caller.role == Role::Admin
+    } else {
+        group
+            .members
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(&caller.username))
+    };
+    if !allowed {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "only members, or admins for open groups, can change retention",
+        ));
+    }
+    group.retention = retention;
+    tracing::info!(
+        "{} set the retention of {} to {:?}",
+        caller.username,
+        name,
+        retention
+    );
+    Ok(Json(group.clone()))
+}
+
+// A page of a group's stored messages, for its members
+#[utoipa::path(
+    get,
+    path = "/groups/{name}/messages",
+    tag = "groups",
+    params(("name" = String, Path, description = "Group name"), HistoryQuery),
+    security((), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Messages, oldest first", body = HistoryPage),
+        (status = 400, description = "Invalid query string", body = ErrorBody),
+        (status = 401, description = "Unknown session token", body = ErrorBody),
+        (status = 403, description = "Not a member of the group", body = ErrorBody),
+        (status = 404, description = "No such group", body = ErrorBody),
+        (status = 422, description = "Invalid query", body = ErrorBody),
+    )
+)]
+pub async fn group_messages(
+    State(state): State<AppState>,
+    caller: Option<AuthUser>,
+    Path(name): Path<String>,
+    ApiQuery(query): ApiQuery<HistoryQuery>,
+) -> Result<Json<HistoryPage>, ApiError> {
+    let username = caller.map(|AuthUser(user)| user.username);
+    read_history(&state, &name, username.as_deref(), &query).map(Json)
+}
+
+/// A page of `group`'s history, if `username` may read the group. Shared by
+/// the HTTP route and WebSocket history requests.
+pub fn read_history(
+    state: &AppState,
+    group: &str,
+    username: Option<&str>,
+    query: &HistoryQuery,
+) -> Result<HistoryPage, ApiError> {
+    {
+        let groups = state.group_state.lock()?;
+        if !groups.groups.contains_key(group) {
+            return Err(group_not_found(group));
+        }
+        if !groups.is_member(group, username) {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "only members can read this group",
+            ));
+        }
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_query",
+            "limit must be at least 1",
+        ));
+    }
+    let limit = limit.min(state.config.history.max_page_size);
+    Ok(state
+        .history
+        .lock()?
+        .page(group, query.before, query.after, limit))
+}
+
+pub fn group_not_found(name: &str) -> ApiError {
+    let message = format!("no group named '{}'", name);
+    ApiError::new(StatusCode::NOT_FOUND, "group_not_found", message)
+}
@@ -1 +1,75 @@
+pub mod blob;
+pub mod error;
+pub mod group;
+pub mod presence;
 pub mod user;
+
+pub use self::error::{ApiError, ApiJson, ApiQuery};
+
+use crate::proto::User;
+use crate::server::rate_limit::{Limited, Subject};
+use crate::server::state::AppState;
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+};
+
+/// Charge `action` to `subjects`, refusing the request with 429 if any of
+/// them is over its limit.
+pub fn throttle(state: &AppState, action: Limited, subjects: &[Subject]) -> Result<(), ApiError> {
+    state
+        .limiter
+        .lock()?
+        .check(action, subjects)
+        .map_err(ApiError::rate_limited)
+}
+
+/// The user behind the `Authorization: Bearer <token>` header, from a
+/// session started with `POST /login`. Rejects the request with 401
+/// otherwise.
+pub struct AuthUser(pub User);
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        let user =
+            <AuthUser as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state)
+                .await?;
+        user.ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "log in and pass the session token as a bearer token",
+            )
+        })
+    }
+}
+
+/// As `Option<AuthUser>`, for routes anonymous callers may use too. A token
+/// that doesn't resolve is still refused.
+impl OptionalFromRequestParts<AppState> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Option<Self>, ApiError> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let Some(token) = token else {
+            return Ok(None);
+        };
+        match state.user_state.lock()?.session_user(token) {
+            Some(user) => Ok(Some(AuthUser(user))),
+            None => Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "unknown or expired session token",
+            )),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::proto::{ErrorBody, Presence, SetPresencePayload};
+use crate::server::api::user::user_not_found;
+use crate::server::api::{ApiError, ApiJson, AuthUser};
+use crate::server::presence::{self, Audience};
+use crate::server::state::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+
+// --- Presence Handlers ---
+
+// A user's presence as the caller may see it. Like presence frames, only
+// users who share a private group with them may look.
+#[utoipa::path(
+    get,
+    path = "/users/{id}/presence",
+    tag = "presence",
+    params(("id" = String, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Presence; offline if the user hides it", body = Presence),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Caller shares no private group with the user", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+pub async fn get_presence(
+    State(state): State<AppState>,
+    AuthUser(caller): AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Presence>, ApiError> {
+    let user = state
+        .user_state
+        .lock()?
+        .users
+        .get(&id)
+        .cloned()
+        .ok_or_else(user_not_found)?;
+    if caller.id != user.id
+        && !state
+            .group_state
+            .lock()?
+            .share_private_group(&caller.username, &user.username)
+    {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "you share no private group with this user",
+        ));
+    }
+    Ok(Json(state.connections.lock()?.presence(&user, &caller.id)))
+}
+
+// Go away or come back; only the user themself may
+#[utoipa::path(
+    put,
+    path = "/users/{id}/presence",
+    tag = "presence",
+    params(("id" = String, Path, description = "User id")),
+    request_body = SetPresencePayload,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Presence after the change", body = Presence),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Not the caller's account", body = ErrorBody),
+    )
+)]
+pub async fn set_presence(
+    State(state): State<AppState>,
+    AuthUser(caller): AuthUser,
+    Path(id): Path<String>,
+    ApiJson(payload): ApiJson<SetPresencePayload>,
+) -> Result<Json<Presence>, ApiError> {
+    if caller.id != id {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "you can only set your own presence",
+        ));
+    }
+    let changed = state.connections.lock()?.set_away(&id, payload.away);
+    if changed {
+        presence::announce(&state, &id, Audience::OthersAndSelf);
+    }
+    Ok(Json(
+        state.connections.lock()?.presence(&caller, &caller.id),
+    ))
+}
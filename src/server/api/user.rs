@@ -1,54 +1,332 @@
-use crate::server::state::{AppState, CreateUserPayload, User};
+use crate::proto::{
+    CreateUserPayload, ErrorBody, LoginPayload, LoginResponse, Role, UpdateUserPayload, User,
+    CLOSE_ACCOUNT_DELETED,
+};
+use crate::server::api::{throttle, ApiError, ApiJson, AuthUser};
+use crate::server::password::{validate_password, PasswordHash};
+use crate::server::presence::{self, Audience};
+use crate::server::rate_limit::{Limited, Subject};
+use crate::server::state::{new_user, AppState, Control};
 use axum::{
-    extract::{Json, Path, State},
+    extract::{ConnectInfo, Json, Path, State},
     http::StatusCode,
-    response::IntoResponse,
 };
-// Import state structs
+use std::net::SocketAddr;
+
+// --- Profile policy ---
+
+pub const USERNAME_MAX_LEN: usize = 32;
+pub const DISPLAY_NAME_MAX_LEN: usize = 64;
+pub const STATUS_TEXT_MAX_LEN: usize = 140;
+
+/// Usernames are 1-32 ASCII letters, digits, '_', '-' or '.', starting with a
+/// letter or digit. Sticking to ASCII keeps look-alike names from other
+/// scripts out; display names are free-form instead.
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.is_empty() || username.len() > USERNAME_MAX_LEN {
+        return Err(format!(
+            "username must be 1 to {} characters",
+            USERNAME_MAX_LEN
+        ));
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("username must start with a letter or digit".to_string());
+    }
+    if let Some(c) = username
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '_' | '-' | '.'))
+    {
+        return Err(format!(
+            "username may only contain letters, digits, '_', '-' and '.', not {:?}",
+            c
+        ));
+    }
+    Ok(())
+}
+
+/// Free text shown to other users: bounded and without control characters.
+fn validate_text(field: &str, text: &str, max_len: usize) -> Result<(), String> {
+    if text.chars().count() > max_len {
+        return Err(format!("{} must be at most {} characters", field, max_len));
+    }
+    if text.chars().any(char::is_control) {
+        return Err(format!("{} must not contain control characters", field));
+    }
+    Ok(())
+}
+
+fn validate_avatar_hash(hash: &str) -> Result<(), String> {
+    if hash.len() == 64 && hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        Ok(())
+    } else {
+        Err("avatar_hash must be a lowercase hex SHA-256".to_string())
+    }
+}
 
 // --- User CRUD Handlers ---
 
-// Create a new user
+// Create a new user. Admin accounts come from the server config, and their
+// names stay reserved even if the account is deleted.
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = CreateUserPayload,
+    responses(
+        (status = 201, description = "User created", body = User),
+        (status = 409, description = "Username taken or reserved, ignoring case", body = ErrorBody),
+        (status = 422, description = "Invalid username or password", body = ErrorBody),
+        (status = 429, description = "Too many sign-ups from this address", body = ErrorBody),
+    )
+)]
 pub async fn create_user(
     State(state): State<AppState>,
-    Json(payload): Json<CreateUserPayload>,
-) -> impl IntoResponse {
-    let mut user_state = state.user_state.lock().unwrap();
-    let user_id = user_state.next_user_id;
-    user_state.next_user_id += 1;
-    user_state.users.insert(user_id, payload.username.clone());
-
-    let new_user = User {
-        id: user_id,
-        username: payload.username,
-    };
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    ApiJson(payload): ApiJson<CreateUserPayload>,
+) -> Result<(StatusCode, Json<User>), ApiError> {
+    throttle(&state, Limited::CreateUser, &[Subject::Ip(peer.ip())])?;
+    if let Err(message) = validate_username(&payload.username) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_username",
+            message,
+        ));
+    }
+    if let Err(message) = validate_password(&payload.password) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_password",
+            message,
+        ));
+    }
+    if state.config.is_admin(&payload.username) {
+        let message = format!("username '{}' is reserved", payload.username);
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "username_taken",
+            message,
+        ));
+    }
+    let password = payload.password;
+    let hash = tokio::task::spawn_blocking(move || PasswordHash::new(&password))
+        .await
+        .map_err(ApiError::internal)?;
 
-    (StatusCode::CREATED, Json(new_user))
+    let mut user_state = state.user_state.lock()?;
+    if let Some(existing) = user_state.find_by_username(&payload.username) {
+        let message = format!("username '{}' is taken", existing.username);
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "username_taken",
+            message,
+        ));
+    }
+    let new_user = new_user(payload.username, Role::User);
+    user_state.insert(new_user.clone(), hash);
+
+    Ok((StatusCode::CREATED, Json(new_user)))
 }
 
-// List all users
-pub async fn list_users(State(state): State<AppState>) -> impl IntoResponse {
-    let user_state = state.user_state.lock().unwrap();
-    let users: Vec<User> = user_state
-        .users
-        .iter()
-        .map(|(id, username)| User {
-            id: *id,
-            username: username.clone(),
-        })
-        .collect();
-    Json(users)
+// List all users, ordered by username
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    responses((status = 200, description = "All users", body = [User]))
+)]
+pub async fn list_users(State(state): State<AppState>) -> Result<Json<Vec<User>>, ApiError> {
+    let user_state = state.user_state.lock()?;
+    let mut users: Vec<User> = user_state.users.values().cloned().collect();
+    users.sort_by(|a, b| a.username.cmp(&b.username));
+    Ok(Json(users))
 }
 
-// Delete a user
+// Update profile fields; only the account's owner may
+#[utoipa::path(
+    patch,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User id")),
+    request_body = UpdateUserPayload,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Updated user", body = User),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Not the caller's account", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+        (status = 422, description = "Invalid profile field", body = ErrorBody),
+    )
+)]
+pub async fn update_user(
+    State(state): State<AppState>,
+    AuthUser(caller): AuthUser,
+    Path(id): Path<String>,
+    ApiJson(payload): ApiJson<UpdateUserPayload>,
+) -> Result<Json<User>, ApiError> {
+    let checks = [
+        payload
+            .display_name
+            .as_deref()
+            .map(|t| validate_text("display_name", t, DISPLAY_NAME_MAX_LEN)),
+        payload
+            .status_text
+            .as_deref()
+            .map(|t| validate_text("status_text", t, STATUS_TEXT_MAX_LEN)),
+        payload
+            .avatar_hash
+            .as_deref()
+            .filter(|h| !h.is_empty())
+            .map(validate_avatar_hash),
+    ];
+    if let Some(Err(message)) = checks.into_iter().flatten().find(Result::is_err) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_profile",
+            message,
+        ));
+    }
+
+    let mut user_state = state.user_state.lock()?;
+    let user = user_state.users.get_mut(&id).ok_or_else(user_not_found)?;
+    if caller.id != user.id {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "you can only edit your own profile",
+        ));
+    }
+
+    // An empty string clears the field
+    let apply = |field: &mut Option<String>, value: Option<String>| {
+        if let Some(value) = value {
+            *field = Some(value).filter(|v| !v.is_empty());
+        }
+    };
+    apply(&mut user.display_name, payload.display_name);
+    apply(&mut user.avatar_hash, payload.avatar_hash);
+    apply(&mut user.status_text, payload.status_text);
+    let visibility_changed = payload
+        .hide_presence
+        .is_some_and(|hide| std::mem::replace(&mut user.hide_presence, hide) != hide);
+    if let Some(hide) = payload.hide_receipts {
+        user.hide_receipts = hide;
+    }
+    let user = user.clone();
+    drop(user_state);
+
+    if visibility_changed {
+        presence::announce(&state, &user.id, Audience::Everyone);
+    }
+    Ok(Json(user))
+}
+
+// Delete an account; only its owner or an admin may. Sessions, group
+// memberships, open sockets and upload quota go with it.
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Neither the owner nor an admin", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
 pub async fn delete_user(
     State(state): State<AppState>,
-    Path(id): Path<usize>,
-) -> impl IntoResponse {
-    let mut user_state = state.user_state.lock().unwrap();
-    if user_state.users.remove(&id).is_some() {
-        StatusCode::NO_CONTENT
-    } else {
-        StatusCode::NOT_FOUND
+    AuthUser(caller): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if caller.id != id && caller.role != Role::Admin {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "only admins can delete other accounts",
+        ));
     }
+    let user = {
+        let mut user_state = state.user_state.lock()?;
+        user_state.remove(&id).ok_or_else(user_not_found)?
+    };
+    let emptied = state.group_state.lock()?.remove_member(&user.username);
+    for group in emptied {
+        state.history.lock()?.forget_group(&group);
+    }
+    // Blobs stay for messages that point at them. Follow-up: drop key packages
+    // and mailboxes here too once the server stores them.
+    state.blobs.lock()?.forget_user(&user.id);
+    let closed = state.connections.lock()?.send_to_user(
+        &user.id,
+        Control::Close {
+            code: CLOSE_ACCOUNT_DELETED,
+            reason: "account deleted",
+        },
+    );
+    tracing::info!(
+        "{} deleted user {} ({}), closing {} connection(s)",
+        caller.username,
+        user.username,
+        user.id,
+        closed
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Start a session for an existing user
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "users",
+    request_body = LoginPayload,
+    responses(
+        (status = 200, description = "Session started", body = LoginResponse),
+        (status = 401, description = "Unknown user or wrong password", body = ErrorBody),
+        (status = 429, description = "Too many attempts from this address or for this user", body = ErrorBody),
+    )
+)]
+pub async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    ApiJson(payload): ApiJson<LoginPayload>,
+) -> Result<Json<LoginResponse>, ApiError> {
+    // Charged per username whether or not it exists, so the limit can't be
+    // used to find out which names are taken
+    let account = Subject::Account(payload.username.to_ascii_lowercase());
+    throttle(&state, Limited::Login, &[Subject::Ip(peer.ip()), account])?;
+    let (id, hash) = {
+        let user_state = state.user_state.lock()?;
+        let user = user_state.find_by_username(&payload.username);
+        let hash = user
+            .and_then(|u| user_state.credentials.get(&u.id))
+            .cloned();
+        (user.map(|u| u.id.clone()), hash)
+    };
+    // Hash outside the lock; it takes a while on purpose
+    let password = payload.password;
+    let valid = tokio::task::spawn_blocking(move || hash.is_some_and(|h| h.verify(&password)))
+        .await
+        .map_err(ApiError::internal)?;
+
+    let mut user_state = state.user_state.lock()?;
+    let Some(user) = id
+        .filter(|_| valid)
+        .and_then(|id| user_state.users.get(&id).cloned())
+    else {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_credentials",
+            "wrong username or password",
+        ));
+    };
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    user_state.sessions.insert(token.clone(), user.id.clone());
+
+    Ok(Json(LoginResponse { token, user }))
+}
+
+pub fn user_not_found() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "user_not_found", "no such user")
 }
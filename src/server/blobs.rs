@@ -0,0 +1,90 @@
+use crate::hex;
+use ring::digest::{digest, SHA256};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+// --- Blob store ---
+//
+// Attachments are uploaded as opaque blobs, kept one file each and named by
+// the SHA-256 of their contents, so identical uploads share a file and
+// downloads can be checked against their name. Clients encrypt blobs before
+// uploading them, but the keys travel in plaintext message bodies, so the
+// server can read them until messages are encrypted.
+//
+// Only the quota bookkeeping lives here, behind the state lock; the files
+// themselves are read and written by the handlers without holding it.
+
+#[derive(Debug)]
+pub struct BlobStore {
+    dir: PathBuf,
+    usage: HashMap<String, u64>,        // User id -> bytes charged
+    uploads: HashSet<(String, String)>, // (User id, hash) pairs charged
+}
+
+impl BlobStore {
+    pub fn new(dir: PathBuf) -> Self {
+        BlobStore {
+            dir,
+            usage: HashMap::new(),
+            uploads: HashSet::new(),
+        }
+    }
+
+    /// The file holding blob `hash`, which must be a valid hash.
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    /// Charge `user_id` for a `size`-byte blob unless they uploaded it
+    /// before. Returns whether they were charged, or the bytes they have
+    /// already used if it would take them over `quota`.
+    pub fn charge(
+        &mut self,
+        user_id: &str,
+        hash: &str,
+        size: u64,
+        quota: u64,
+    ) -> Result<bool, u64> {
+        let key = (user_id.to_string(), hash.to_string());
+        if self.uploads.contains(&key) {
+            return Ok(false);
+        }
+        let used = self.usage.get(user_id).copied().unwrap_or_default();
+        if used.saturating_add(size) > quota {
+            return Err(used);
+        }
+        self.usage.insert(user_id.to_string(), used + size);
+        self.uploads.insert(key);
+        Ok(true)
+    }
+
+    /// Undo a `charge` whose upload could not be stored.
+    pub fn refund(&mut self, user_id: &str, hash: &str, size: u64) {
+        if self
+            .uploads
+            .remove(&(user_id.to_string(), hash.to_string()))
+        {
+            if let Some(used) = self.usage.get_mut(user_id) {
+                *used = used.saturating_sub(size);
+            }
+        }
+    }
+
+    /// Drop a deleted user's accounting. Their blobs stay, since messages
+    /// others received may still point at them.
+    pub fn forget_user(&mut self, user_id: &str) {
+        self.usage.remove(user_id);
+        self.uploads.retain(|(owner, _)| owner != user_id);
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`, the name a blob is stored under.
+pub fn blob_hash(bytes: &[u8]) -> String {
+    hex::encode(digest(&SHA256, bytes).as_ref())
+}
+
+/// Whether `hash` could name a blob: 64 lowercase hex digits, which also
+/// keeps it from escaping the blob directory.
+pub fn is_blob_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
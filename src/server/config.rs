@@ -0,0 +1,275 @@
+use crate::server::api::user::validate_username;
+use crate::server::password::PasswordHash;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// --- Server config file ---
+//
+// Read from `veil server --config PATH`. Everything is optional:
+//
+//     [[admins]]
+//     username = "root"
+//     password_hash = "pbkdf2-sha256$100000$..."  # From `veil hash-password`
+//
+//     [rate_limits]
+//     frames_per_connection = { burst = 20, per_minute = 300 }
+//     logins_per_ip = { burst = 10, per_minute = 10 }
+//
+//     [websocket]
+//     max_message_bytes = 65536
+//     ping_interval_ms = 20000
+//
+//     [history]
+//     max_messages_per_group = 10000
+//     sweep_interval_ms = 60000
+//
+//     [blobs]
+//     dir = "/var/lib/veil/blobs"
+//     max_blob_bytes = 10485760
+
+/// Settings fixed when the server starts.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Accounts with the admin role, created when the server starts. Their
+    /// names can't be registered by anyone else.
+    pub admins: Vec<AdminAccount>,
+    pub rate_limits: RateLimits,
+    pub websocket: WebSocketConfig,
+    pub history: HistoryConfig,
+    pub blobs: BlobConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminAccount {
+    pub username: String,
+    pub password_hash: PasswordHash,
+}
+
+/// Where uploaded attachments are kept and how much each user may upload.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlobConfig {
+    /// Directory holding one file per blob, named by its hash. Defaults to
+    /// one under the system's temporary directory.
+    pub dir: PathBuf,
+    /// Largest single upload.
+    pub max_blob_bytes: usize,
+    /// Total a user may upload; uploading the same blob twice counts once.
+    pub quota_bytes_per_user: u64,
+}
+
+impl Default for BlobConfig {
+    fn default() -> Self {
+        BlobConfig {
+            dir: std::env::temp_dir().join("veil-blobs"),
+            max_blob_bytes: 10 * 1024 * 1024,
+            quota_bytes_per_user: 100 * 1024 * 1024,
+        }
+    }
+}
+
+/// How much group history the server keeps and hands out at once.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Older messages are dropped once a group has this many.
+    pub max_messages_per_group: usize,
+    /// Largest `limit` a history request may ask for; larger ones get this.
+    pub max_page_size: usize,
+    /// How often groups' retention settings are applied.
+    pub sweep_interval_ms: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            max_messages_per_group: 10_000,
+            max_page_size: 200,
+            sweep_interval_ms: 60_000,
+        }
+    }
+}
+
+/// Size limits on incoming WebSocket traffic, and the heartbeat that finds
+/// dead connections. Larger frames or messages close the connection with code
+/// 1009; missing too many pongs in a row closes it with 1001.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// A whole message, after reassembling fragmented frames.
+    pub max_message_bytes: usize,
+    /// A single frame.
+    pub max_frame_bytes: usize,
+    /// How often the server pings each connection.
+    pub ping_interval_ms: u64,
+    /// Unanswered pings in a row before the connection is dropped.
+    pub max_missed_pongs: u32,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            max_message_bytes: 64 * 1024,
+            max_frame_bytes: 64 * 1024,
+            ping_interval_ms: 20_000,
+            max_missed_pongs: 3,
+        }
+    }
+}
+
+/// A token bucket: up to `burst` actions at once, refilled at `per_minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Rate {
+    pub const fn new(burst: u32, per_minute: u32) -> Self {
+        Rate { burst, per_minute }
+    }
+}
+
+/// How often clients may do the things that cost the server the most.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// WebSocket frames from a single connection.
+    pub frames_per_connection: Rate,
+    /// WebSocket frames from all of one user's connections.
+    pub frames_per_user: Rate,
+    /// WebSocket frames from all connections from one IP address.
+    pub frames_per_ip: Rate,
+    /// `POST /users` from one IP address.
+    pub user_creation_per_ip: Rate,
+    /// `POST /login` from one IP address.
+    pub logins_per_ip: Rate,
+    /// `POST /login` naming one username, from anywhere.
+    pub logins_per_account: Rate,
+    /// Typing frames from a single connection, on top of the frame limits.
+    pub typing_per_connection: Rate,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            frames_per_connection: Rate::new(20, 300),
+            frames_per_user: Rate::new(30, 600),
+            frames_per_ip: Rate::new(60, 1200),
+            user_creation_per_ip: Rate::new(5, 2),
+            logins_per_ip: Rate::new(10, 10),
+            logins_per_account: Rate::new(10, 5),
+            typing_per_connection: Rate::new(3, 30),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Read a config file; see the top of this module for the format.
+    pub fn load(path: &Path) -> Result<ServerConfig, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config {}: {}", path.display(), e))?;
+        let config: ServerConfig = toml::from_str(&text)
+            .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+        config.validate_admins()?;
+        config.rate_limits.validate()?;
+        config.websocket.validate()?;
+        config.history.validate()?;
+        config.blobs.validate()?;
+        Ok(config)
+    }
+
+    /// Matches the way usernames are compared everywhere else: ignoring case.
+    pub fn is_admin(&self, username: &str) -> bool {
+        self.admins
+            .iter()
+            .any(|admin| admin.username.eq_ignore_ascii_case(username))
+    }
+
+    fn validate_admins(&self) -> Result<(), String> {
+        for (i, admin) in self.admins.iter().enumerate() {
+            validate_username(&admin.username)
+                .map_err(|e| format!("admins.{}: {}", admin.username, e))?;
+            if self.admins[..i]
+                .iter()
+                .any(|other| other.username.eq_ignore_ascii_case(&admin.username))
+            {
+                return Err(format!("admins.{}: listed twice", admin.username));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RateLimits {
+    fn validate(&self) -> Result<(), String> {
+        let rates = [
+            ("frames_per_connection", self.frames_per_connection),
+            ("frames_per_user", self.frames_per_user),
+            ("frames_per_ip", self.frames_per_ip),
+            ("user_creation_per_ip", self.user_creation_per_ip),
+            ("logins_per_ip", self.logins_per_ip),
+            ("logins_per_account", self.logins_per_account),
+            ("typing_per_connection", self.typing_per_connection),
+        ];
+        match rates
+            .iter()
+            .find(|(_, r)| r.burst == 0 || r.per_minute == 0)
+        {
+            Some((name, _)) => Err(format!(
+                "rate_limits.{}: burst and per_minute must be at least 1",
+                name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl WebSocketConfig {
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_millis(self.ping_interval_ms)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_message_bytes == 0 || self.max_frame_bytes == 0 {
+            Err("websocket: sizes must be at least 1 byte".to_string())
+        } else if self.max_frame_bytes > self.max_message_bytes {
+            Err("websocket: max_frame_bytes exceeds max_message_bytes".to_string())
+        } else if self.ping_interval_ms == 0 || self.max_missed_pongs == 0 {
+            Err("websocket: ping_interval_ms and max_missed_pongs must be at least 1".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn sweep_interval(&self) -> Duration {
+        Duration::from_millis(self.sweep_interval_ms)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_messages_per_group == 0 || self.max_page_size == 0 {
+            Err("history: max_messages_per_group and max_page_size must be at least 1".to_string())
+        } else if self.sweep_interval_ms == 0 {
+            Err("history: sweep_interval_ms must be at least 1".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl BlobConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.max_blob_bytes == 0 || self.quota_bytes_per_user == 0 {
+            Err("blobs: max_blob_bytes and quota_bytes_per_user must be at least 1".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::proto::{ChatMessage, HistoryPage, Retention};
+use crate::server::state::{now_millis, AppState};
+use std::collections::{HashMap, VecDeque};
+use std::sync::PoisonError;
+use tokio::time::MissedTickBehavior;
+
+// --- Stored group history ---
+//
+// Group messages are kept per group and numbered from 1 in the order they
+// were relayed, so clients can page through them with sequence numbers as
+// cursors. Bodies are stored as they arrived and never looked at, so they can
+// be ciphertext. Direct messages are not stored.
+//
+// Each group may also bound its history by age and count; a background task
+// applies those bounds every `sweep_interval_ms`.
+
+/// Page size when a request doesn't say.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct History {
+    groups: HashMap<String, GroupHistory>, // Group name -> its messages
+    max_per_group: usize,
+}
+
+#[derive(Debug, Default)]
+struct GroupHistory {
+    last_seq: u64,
+    messages: VecDeque<ChatMessage>, // Ordered by `seq`
+}
+
+impl History {
+    pub fn new(max_per_group: usize) -> Self {
+        History {
+            groups: HashMap::new(),
+            max_per_group,
+        }
+    }
+
+    /// Give `chat` the next sequence number in `group` and keep a copy,
+    /// dropping the oldest message if the group is full.
+    pub fn append(&mut self, group: &str, chat: &mut ChatMessage) {
+        let history = self.groups.entry(group.to_string()).or_default();
+        history.last_seq += 1;
+        chat.seq = Some(history.last_seq);
+        history.messages.push_back(chat.clone());
+        if history.messages.len() > self.max_per_group {
+            history.messages.pop_front();
+        }
+    }
+
+    /// Up to `limit` messages strictly between `after` and `before`: the
+    /// oldest of them if `after` is given, else the newest.
+    pub fn page(
+        &self,
+        group: &str,
+        before: Option<u64>,
+        after: Option<u64>,
+        limit: usize,
+    ) -> HistoryPage {
+        let in_range = |chat: &&ChatMessage| {
+            let seq = chat.seq.unwrap_or_default();
+            before.is_none_or(|before| seq < before) && after.is_none_or(|after| seq > after)
+        };
+        let matching: Vec<&ChatMessage> = self
+            .groups
+            .get(group)
+            .map(|history| history.messages.iter().filter(in_range).collect())
+            .unwrap_or_default();
+        let more = matching.len() > limit;
+        let page = if after.is_some() {
+            &matching[..limit.min(matching.len())]
+        } else {
+            &matching[matching.len().saturating_sub(limit)..]
+        };
+        HistoryPage {
+            group: group.to_string(),
+            messages: page.iter().map(|chat| (*chat).clone()).collect(),
+            more,
+        }
+    }
+
+    /// Drop `group`'s messages that `retention` no longer allows keeping at
+    /// `now`; returns how many went. Sequence numbers are not reused.
+    pub fn apply_retention(&mut self, group: &str, retention: &Retention, now: u64) -> usize {
+        let Some(history) = self.groups.get_mut(group) else {
+            return 0;
+        };
+        let before = history.messages.len();
+        if let Some(max_age_secs) = retention.max_age_secs {
+            let cutoff = now.saturating_sub(max_age_secs.saturating_mul(1000));
+            // Messages are stored in the order they were stamped
+            while history
+                .messages
+                .front()
+                .is_some_and(|chat| chat.sent_at.unwrap_or_default() < cutoff)
+            {
+                history.messages.pop_front();
+            }
+        }
+        if let Some(max_count) = retention.max_count {
+            let excess = history.messages.len().saturating_sub(max_count);
+            history.messages.drain(..excess);
+        }
+        before - history.messages.len()
+    }
+
+    /// Drop a deleted group's messages, so a new group by the same name
+    /// doesn't show them to its members.
+    pub fn forget_group(&mut self, group: &str) {
+        self.groups.remove(group);
+    }
+}
+
+/// Apply every group's retention settings each sweep interval, for as long
+/// as the server runs.
+pub async fn sweep(state: AppState) {
+    let mut ticks = tokio::time::interval(state.config.history.sweep_interval());
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        // Copy the settings out rather than hold both locks
+        let policies: Vec<(String, Retention)> = state
+            .group_state
+            .lock()
+            .unwrap_or_else(recover)
+            .groups
+            .values()
+            .filter(|group| !group.retention.is_unbounded())
+            .map(|group| (group.name.clone(), group.retention))
+            .collect();
+        let mut history = state.history.lock().unwrap_or_else(recover);
+        let now = now_millis();
+        for (group, retention) in &policies {
+            let dropped = history.apply_retention(group, retention, now);
+            if dropped > 0 {
+                tracing::debug!("Retention dropped {} message(s) from {}", dropped, group);
+            }
+        }
+    }
+}
+
+// A handler that panicked leaves the maps usable, and giving up would stop
+// retention for good, so the sweep carries on through poisoned locks.
+fn recover<T>(poisoned: PoisonError<T>) -> T {
+    tracing::warn!("Retention sweep using a poisoned lock: {}", poisoned);
+    poisoned.into_inner()
+}
@@ -1,24 +1,39 @@
 pub mod api;
+pub mod blobs;
+pub mod config;
+pub mod history;
+pub mod openapi;
+pub mod password;
+pub mod presence;
+pub mod rate_limit;
+pub mod request_id;
 pub mod state;
+pub mod version;
 pub mod websocket;
 
-use axum::{
-    routing::{any, delete, get, post},
-    serve, Router,
-};
-use std::{
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-};
-use tokio::sync::broadcast;
+use crate::proto::{Role, API_PREFIX};
+use axum::{extract::State, http::StatusCode, middleware, routing::get, serve, Json, Router};
+use std::{io, net::SocketAddr};
+use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
 
+use self::api::blob as blob_api;
+use self::api::group as group_api;
+use self::api::presence as presence_api;
 use self::api::user as user_api;
-use self::state::{AppState, UserState};
-use self::websocket::ws_handler;
+use self::api::{ApiError, AuthUser};
+pub use self::config::ServerConfig;
+use self::openapi::ApiDoc;
+use self::state::AppState;
+use self::websocket as ws_api;
 
-pub async fn run_server() {
+/// Address `run_server` listens on.
+pub const DEFAULT_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 3000);
+
+pub async fn run_server(config: ServerConfig) -> io::Result<()> {
     tracing_subscriber::registry()
         .with(EnvFilter::new(
             // Use imported EnvFilter directly
@@ -28,35 +43,165 @@ pub async fn run_server() {
         .with(fmt::layer()) // Use imported fmt
         .init();
 
-    let (tx, _rx) = broadcast::channel(100);
-
-    let app_state = AppState {
-        user_state: Arc::new(Mutex::new(UserState::default())), // Use imported Mutex
-        tx: Arc::new(tx),
-    };
-
-    let app = Router::new()
-        .route("/ws", any(ws_handler).with_state(app_state.clone()))
-        .route(
-            "/users",
-            post(user_api::create_user)
-                .get(user_api::list_users)
-                .with_state(app_state.clone()),
-        )
-        .route(
-            "/users/:id",
-            delete(user_api::delete_user).with_state(app_state.clone()),
-        )
-        .with_state(app_state.clone())
-        .layer(TraceLayer::new_for_http());
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
+    let addr = SocketAddr::from(DEFAULT_ADDR);
     tracing::debug!("Server listening on {}", addr);
 
+    let state = AppState::with_config(config);
+    tokio::spawn(history::sweep(state.clone()));
     serve(
-        tokio::net::TcpListener::bind(addr).await.unwrap(),
-        app.into_make_service(),
+        TcpListener::bind(addr).await?,
+        router(state).into_make_service_with_connect_info::<SocketAddr>(),
     )
     .await
-    .unwrap();
+}
+
+/// All HTTP and WebSocket routes, serving `app_state`, plus the OpenAPI
+/// document at `/openapi.json` and metrics at `/metrics`. Rate limits need
+/// the peer address, so serve it with
+/// `into_make_service_with_connect_info::<SocketAddr>()`.
+///
+/// Routes live under [`API_PREFIX`]. The unprefixed paths predate versioning
+/// and stay as aliases of v1, so clients deployed before it keep working.
+pub fn router(app_state: AppState) -> Router {
+    let (versioned, openapi) = versioned_routes(app_state.clone()).split_for_parts();
+    let (legacy, _) = api_routes(app_state.clone()).split_for_parts();
+    versioned
+        .merge(legacy)
+        .route("/openapi.json", get(move || async move { Json(openapi) }))
+        .route("/metrics", get(metrics).with_state(app_state))
+        .fallback(|| async {
+            ApiError::new(StatusCode::NOT_FOUND, "not_found", "no such endpoint")
+        })
+        .method_not_allowed_fallback(|| async {
+            ApiError::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "method_not_allowed",
+                "method not allowed on this endpoint",
+            )
+        })
+        .layer(middleware::from_fn(version::negotiate))
+        .layer(TraceLayer::new_for_http())
+        // Outermost, so the request ID is known everywhere else
+        .layer(middleware::from_fn(request_id::assign))
+}
+
+/// Counters in the Prometheus text format, for admins only: scrape with an
+/// admin's session token as the bearer token.
+async fn metrics(
+    State(state): State<AppState>,
+    AuthUser(caller): AuthUser,
+) -> Result<String, ApiError> {
+    if caller.role != Role::Admin {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "only admins can read metrics",
+        ));
+    }
+    Ok(state.limiter.lock()?.metrics())
+}
+
+/// The OpenAPI document `router` serves at `/openapi.json`.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    versioned_routes(AppState::new()).into_openapi()
+}
+
+fn versioned_routes(app_state: AppState) -> OpenApiRouter {
+    OpenApiRouter::with_openapi(ApiDoc::openapi()).nest(API_PREFIX, api_routes(app_state))
+}
+
+// Registering through `routes!` documents each route as it's added, so new
+// endpoints show up in the OpenAPI document as long as their handler has a
+// `#[utoipa::path]` attribute
+fn api_routes(app_state: AppState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(ws_api::ws_handler))
+        .routes(routes!(user_api::create_user, user_api::list_users))
+        .routes(routes!(user_api::login))
+        .routes(routes!(user_api::update_user, user_api::delete_user))
+        .routes(routes!(
+            presence_api::get_presence,
+            presence_api::set_presence
+        ))
+        .routes(routes!(group_api::list_groups, group_api::create_group))
+        .routes(routes!(group_api::group_messages))
+        .routes(routes!(group_api::set_retention))
+        .routes(routes!(blob_api::upload_blob))
+        .routes(routes!(blob_api::download_blob))
+        .with_state(app_state)
+}
+
+// --- In-process server ---
+
+/// A server running on a background task, e.g. inside a test. Dropping it
+/// stops the server too.
+pub struct RunningServer {
+    addr: SocketAddr,
+    state: AppState,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<io::Result<()>>>,
+}
+
+/// Serve fresh state on `addr` in the background. Port 0 picks a free port;
+/// see `RunningServer::addr` for the one chosen. Does not set up tracing.
+pub async fn start_server(addr: SocketAddr, config: ServerConfig) -> io::Result<RunningServer> {
+    let listener = TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+    let state = AppState::with_config(config);
+    let (shutdown, signal) = oneshot::channel::<()>();
+    let app = router(state.clone());
+    let sweeper = tokio::spawn(history::sweep(state.clone()));
+    let task = tokio::spawn(async move {
+        let result = serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async {
+            let _ = signal.await;
+        })
+        .await;
+        sweeper.abort();
+        result
+    });
+    Ok(RunningServer {
+        addr,
+        state,
+        shutdown: Some(shutdown),
+        task: Some(task),
+    })
+}
+
+impl RunningServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base URL for `VeilClient::new`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The live server state, for inspecting or seeding it directly.
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Stop accepting connections and wait for in-flight requests to finish.
+    pub async fn shutdown(mut self) -> io::Result<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        match self.task.take() {
+            Some(task) => task.await.map_err(io::Error::other)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
 }
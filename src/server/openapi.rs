@@ -0,0 +1,51 @@
+use crate::proto::{
+    ChatMessage, ErrorBody, ErrorFrame, HistoryFrame, HistoryRequestFrame, PresenceFrame,
+    ReceiptFrame, Target, TypingFrame, API_VERSION,
+};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi,
+};
+
+/// The parts of the OpenAPI document not attached to a route: metadata, the
+/// bearer scheme and the WebSocket frame schemas, which no HTTP operation
+/// references. Operations are added by `router` as routes are registered.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Veil",
+        description = "Chat server API. Every route is also served without the /v1 \
+                       prefix for clients older than API versioning. Send \
+                       `Veil-Api-Version` to pin a version; the WebSocket speaks \
+                       the `veil.v1` subprotocol and exchanges `ChatMessage`, `TypingFrame` \
+                       and `ReceiptFrame` frames."
+    ),
+    components(schemas(
+        ChatMessage,
+        Target,
+        ErrorBody,
+        ErrorFrame,
+        PresenceFrame,
+        TypingFrame,
+        ReceiptFrame,
+        HistoryRequestFrame,
+        HistoryFrame
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        // The crate has no license field to fill these from
+        openapi.info.version = API_VERSION.to_string();
+        openapi.info.license = None;
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
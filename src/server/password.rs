@@ -0,0 +1,142 @@
+use crate::hex;
+use ring::pbkdf2::{self, PBKDF2_HMAC_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+// --- Password hashing ---
+//
+// Passwords are kept as salted PBKDF2-HMAC-SHA256 hashes, written as
+// `pbkdf2-sha256$ITERATIONS$SALT$HASH` with the salt and hash in hex. Admin
+// accounts are given in the config file in the same form; `veil hash-password`
+// prints one.
+
+pub const PASSWORD_MIN_LEN: usize = 8;
+pub const PASSWORD_MAX_LEN: usize = 1024;
+
+const SCHEME: &str = "pbkdf2-sha256";
+const ITERATIONS: NonZeroU32 = NonZeroU32::new(100_000).unwrap();
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+/// Passwords are 8-1024 characters; anything else goes.
+pub fn validate_password(password: &str) -> Result<(), String> {
+    let len = password.chars().count();
+    if !(PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&len) {
+        return Err(format!(
+            "password must be {} to {} characters",
+            PASSWORD_MIN_LEN, PASSWORD_MAX_LEN
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PasswordHash {
+    iterations: NonZeroU32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    /// Hash `password` under a fresh salt. Deliberately slow, so call it
+    /// off the async runtime.
+    pub fn new(password: &str) -> PasswordHash {
+        let mut salt = vec![0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .expect("system random number generator failed");
+        let mut hash = vec![0; HASH_LEN];
+        pbkdf2::derive(
+            PBKDF2_HMAC_SHA256,
+            ITERATIONS,
+            &salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+        PasswordHash {
+            iterations: ITERATIONS,
+            salt,
+            hash,
+        }
+    }
+
+    /// Whether `password` is the one hashed, compared in constant time. As
+    /// slow as `new`.
+    pub fn verify(&self, password: &str) -> bool {
+        pbkdf2::verify(
+            PBKDF2_HMAC_SHA256,
+            self.iterations,
+            &self.salt,
+            password.as_bytes(),
+            &self.hash,
+        )
+        .is_ok()
+    }
+}
+
+// Never print the hash itself by accident
+impl fmt::Debug for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PasswordHash(..)")
+    }
+}
+
+impl fmt::Display for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}${}${}${}",
+            SCHEME,
+            self.iterations,
+            hex::encode(&self.salt),
+            hex::encode(&self.hash)
+        )
+    }
+}
+
+impl FromStr for PasswordHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "password hash must look like {}$ITERATIONS$SALT$HASH",
+                SCHEME
+            )
+        };
+        let mut parts = s.split('$');
+        let (Some(SCHEME), Some(iterations), Some(salt), Some(hash), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return Err(invalid());
+        };
+        let iterations = iterations.parse().map_err(|_| invalid())?;
+        let (Some(salt), Some(hash)) = (hex::decode(salt), hex::decode(hash)) else {
+            return Err(invalid());
+        };
+        if salt.is_empty() || hash.len() != HASH_LEN {
+            return Err(invalid());
+        }
+        Ok(PasswordHash {
+            iterations,
+            salt,
+            hash,
+        })
+    }
+}
+
+impl TryFrom<String> for PasswordHash {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
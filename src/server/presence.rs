@@ -0,0 +1,76 @@
+use crate::server::state::{AppState, Control};
+
+// --- Presence notifications ---
+//
+// Presence frames go to the users who share a private group with the one
+// whose presence changed; the open groups everyone is in don't count. Anyone hiding their presence is left out, except for the
+// moment they start or stop hiding it, when everyone has to be told.
+
+/// Who hears about a change in a user's presence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Audience {
+    /// Users who share a private group with them, unless they hide their
+    /// presence.
+    Others,
+    /// Those, and the user's own connections.
+    OthersAndSelf,
+    /// Everyone they share a private group with, hidden or not, and their own
+    /// connections.
+    Everyone,
+}
+
+/// Send `user_id`'s current presence to `audience`. Does nothing for users
+/// that no longer exist, e.g. when a deleted account's sockets close.
+pub fn announce(state: &AppState, user_id: &str, audience: Audience) {
+    let Ok(users) = state.user_state.lock() else {
+        return;
+    };
+    let Some(user) = users.users.get(user_id) else {
+        return;
+    };
+    let (Ok(groups), Ok(connections)) = (state.group_state.lock(), state.connections.lock()) else {
+        return;
+    };
+    for viewer_id in connections.online_users() {
+        let Some(viewer) = users.users.get(&viewer_id) else {
+            continue;
+        };
+        let told = if viewer.id == user.id {
+            audience != Audience::Others
+        } else {
+            (audience == Audience::Everyone || !user.hide_presence)
+                && groups.share_private_group(&viewer.username, &user.username)
+        };
+        if told {
+            let presence = connections.presence(user, &viewer.id);
+            connections.send_to_user(&viewer.id, Control::Presence(presence));
+        }
+    }
+}
+
+/// Send a new connection of `user_id` the presence of its own user, then
+/// of everyone visible it shares a private group with who is online, by
+/// username.
+pub fn introduce(state: &AppState, connection_id: u64, user_id: &str) {
+    let Ok(users) = state.user_state.lock() else {
+        return;
+    };
+    let Some(viewer) = users.users.get(user_id) else {
+        return;
+    };
+    let (Ok(groups), Ok(connections)) = (state.group_state.lock(), state.connections.lock()) else {
+        return;
+    };
+    let mut others: Vec<_> = connections
+        .online_users()
+        .iter()
+        .filter_map(|id| users.users.get(id))
+        .filter(|other| other.id != viewer.id && !other.hide_presence)
+        .filter(|other| groups.share_private_group(&viewer.username, &other.username))
+        .collect();
+    others.sort_by(|a, b| a.username.cmp(&b.username));
+    for user in std::iter::once(viewer).chain(others) {
+        let presence = connections.presence(user, &viewer.id);
+        connections.send(connection_id, Control::Presence(presence));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::server::config::{Rate, RateLimits};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Buckets are pruned once there are this many, dropping those that have
+/// refilled completely and so behave like fresh ones.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Something clients do that costs the server enough to limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Limited {
+    Frame,
+    CreateUser,
+    Login,
+    Typing,
+}
+
+/// Who an action is charged to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subject {
+    Connection(u64),
+    User(String),    // User id
+    Account(String), // Lowercased username, registered or not
+    Ip(IpAddr),
+}
+
+impl Limited {
+    fn name(self) -> &'static str {
+        match self {
+            Limited::Frame => "frame",
+            Limited::CreateUser => "create_user",
+            Limited::Login => "login",
+            Limited::Typing => "typing",
+        }
+    }
+}
+
+impl Subject {
+    fn scope(&self) -> &'static str {
+        match self {
+            Subject::Connection(_) => "connection",
+            Subject::User(_) => "user",
+            Subject::Account(_) => "account",
+            Subject::Ip(_) => "ip",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let refilled = self.tokens + elapsed * f64::from(rate.per_minute) / 60.0;
+        self.tokens = refilled.min(f64::from(rate.burst));
+        self.updated = now;
+    }
+
+    /// How long until a whole token is available; zero if one is now.
+    fn wait(&self, rate: Rate) -> Duration {
+        let missing = (1.0 - self.tokens).max(0.0);
+        Duration::from_secs_f64(missing * 60.0 / f64::from(rate.per_minute))
+    }
+}
+
+/// Token buckets for every (action, subject) pair seen recently, plus a count
+/// of refusals for `/metrics`.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: HashMap<(Limited, Subject), Bucket>,
+    throttled: BTreeMap<(&'static str, &'static str), u64>, // (action, scope) -> count
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// Charge `action` to every subject at once. If any of them is out of
+    /// tokens nothing is charged, and the error says how long to wait.
+    pub fn check(&mut self, action: Limited, subjects: &[Subject]) -> Result<(), Duration> {
+        let now = Instant::now();
+        if self.buckets.len() >= PRUNE_THRESHOLD {
+            self.prune(now);
+        }
+
+        let mut wait = Duration::ZERO;
+        let mut refused = Vec::new();
+        for subject in subjects {
+            let Some(rate) = rate(&self.limits, action, subject) else {
+                continue;
+            };
+            let bucket = self
+                .buckets
+                .entry((action, subject.clone()))
+                .or_insert(Bucket {
+                    tokens: f64::from(rate.burst),
+                    updated: now,
+                });
+            bucket.refill(rate, now);
+            let subject_wait = bucket.wait(rate);
+            if !subject_wait.is_zero() {
+                wait = wait.max(subject_wait);
+                refused.push(subject.scope());
+            }
+        }
+        if !refused.is_empty() {
+            for scope in refused {
+                *self.throttled.entry((action.name(), scope)).or_default() += 1;
+            }
+            return Err(wait);
+        }
+
+        for subject in subjects {
+            if let Some(bucket) = self.buckets.get_mut(&(action, subject.clone())) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget a connection's buckets once it has closed.
+    pub fn forget_connection(&mut self, connection_id: u64) {
+        self.buckets
+            .retain(|(_, subject), _| *subject != Subject::Connection(connection_id));
+    }
+
+    /// Refusal counts in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let mut text = String::from(
+            "# HELP veil_rate_limited_total Requests and frames refused by rate limits.\n\
+             # TYPE veil_rate_limited_total counter\n",
+        );
+        for ((action, scope), count) in &self.throttled {
+            let _ = writeln!(
+                text,
+                "veil_rate_limited_total{{action=\"{}\",scope=\"{}\"}} {}",
+                action, scope, count
+            );
+        }
+        text
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let limits = &self.limits;
+        self.buckets.retain(|(action, subject), bucket| {
+            let Some(rate) = rate(limits, *action, subject) else {
+                return false;
+            };
+            bucket.refill(rate, now);
+            bucket.tokens < f64::from(rate.burst)
+        });
+    }
+}
+
+/// The limit on `action` for `subject`'s kind, if there is one.
+fn rate(limits: &RateLimits, action: Limited, subject: &Subject) -> Option<Rate> {
+    match (action, subject) {
+        (Limited::Frame, Subject::Connection(_)) => Some(limits.frames_per_connection),
+        (Limited::Frame, Subject::User(_)) => Some(limits.frames_per_user),
+        (Limited::Frame, Subject::Ip(_)) => Some(limits.frames_per_ip),
+        (Limited::CreateUser, Subject::Ip(_)) => Some(limits.user_creation_per_ip),
+        (Limited::Login, Subject::Ip(_)) => Some(limits.logins_per_ip),
+        (Limited::Login, Subject::Account(_)) => Some(limits.logins_per_account),
+        (Limited::Typing, Subject::Connection(_)) => Some(limits.typing_per_connection),
+        _ => None,
+    }
+}
@@ -1,33 +1,299 @@
-use serde::{Deserialize, Serialize};
+use crate::proto::{ChatMessage, Group, Retention, Role, User, DEFAULT_GROUP};
+use crate::proto::{ErrorBody, HistoryPage, Presence, PresenceStatus, Receipt, Typing};
+use crate::server::blobs::BlobStore;
+use crate::server::config::ServerConfig;
+use crate::server::history::History;
+use crate::server::password::PasswordHash;
+use crate::server::rate_limit::RateLimiter;
 use std::{
-    // Changed import here
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex}, // Use std::sync::Mutex
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 
 // Shared state for managing users and WebSocket connections
 #[derive(Debug, Clone)]
 pub struct AppState {
-    pub user_state: Arc<Mutex<UserState>>,  // Use std::sync::Mutex
-    pub tx: Arc<broadcast::Sender<String>>, // Broadcast channel for chat messages
+    pub user_state: Arc<Mutex<UserState>>,   // Use std::sync::Mutex
+    pub group_state: Arc<Mutex<GroupState>>, // Group name -> group
+    pub tx: Arc<broadcast::Sender<ChatMessage>>, // Broadcast channel for chat messages
+    pub connections: Arc<Mutex<Connections>>, // Live WebSocket connections
+    pub history: Arc<Mutex<History>>,        // Stored group messages
+    pub blobs: Arc<Mutex<BlobStore>>,        // Upload accounting for attachments
+    pub limiter: Arc<Mutex<RateLimiter>>,
+    pub config: Arc<ServerConfig>,
+}
+
+impl AppState {
+    /// Empty state with the default group.
+    pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+
+    pub fn with_config(config: ServerConfig) -> Self {
+        let (tx, _rx) = broadcast::channel(100);
+        let mut user_state = UserState::default();
+        for admin in &config.admins {
+            let user = new_user(admin.username.clone(), Role::Admin);
+            user_state.insert(user, admin.password_hash.clone());
+        }
+        AppState {
+            user_state: Arc::new(Mutex::new(user_state)),
+            group_state: Arc::new(Mutex::new(GroupState::default())),
+            tx: Arc::new(tx),
+            connections: Arc::new(Mutex::new(Connections::default())),
+            history: Arc::new(Mutex::new(History::new(
+                config.history.max_messages_per_group,
+            ))),
+            blobs: Arc::new(Mutex::new(BlobStore::new(config.blobs.dir.clone()))),
+            limiter: Arc::new(Mutex::new(RateLimiter::new(config.rate_limits.clone()))),
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct UserState {
-    pub users: HashMap<usize, String>, // In-memory user storage (UserId -> Username)
-    pub next_user_id: usize,
-    pub clients: HashMap<usize, broadcast::Sender<String>>, // User ID to sender for private messages (future use)
+    pub users: HashMap<String, User>, // In-memory user storage (UserId -> User)
+    pub sessions: HashMap<String, String>, // Session token -> UserId
+    pub credentials: HashMap<String, PasswordHash>, // UserId -> password hash
+}
+
+impl UserState {
+    pub fn insert(&mut self, user: User, password: PasswordHash) {
+        self.credentials.insert(user.id.clone(), password);
+        self.users.insert(user.id.clone(), user);
+    }
+
+    /// Remove an account along with its password and sessions.
+    pub fn remove(&mut self, id: &str) -> Option<User> {
+        self.credentials.remove(id);
+        self.sessions.retain(|_, user_id| user_id != id);
+        self.users.remove(id)
+    }
+
+    /// Resolve a session token to the user it was issued for.
+    pub fn session_user(&self, token: &str) -> Option<User> {
+        let id = self.sessions.get(token)?;
+        self.users.get(id).cloned()
+    }
+
+    /// Usernames are unique regardless of case, so lookups ignore it too.
+    pub fn find_by_username(&self, username: &str) -> Option<&User> {
+        self.users
+            .values()
+            .find(|u| u.username.eq_ignore_ascii_case(username))
+    }
+}
+
+/// A fresh account with a random id and an empty profile.
+pub fn new_user(username: String, role: Role) -> User {
+    User {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        username,
+        display_name: None,
+        avatar_hash: None,
+        status_text: None,
+        role,
+        hide_presence: false,
+        hide_receipts: false,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupState {
+    pub groups: HashMap<String, Group>, // In-memory group storage (Name -> Group)
+}
+
+impl GroupState {
+    /// Whether `username` may read and post in `group`, ignoring case like
+    /// usernames do. Groups without members are open to everyone, including
+    /// anonymous connections.
+    pub fn is_member(&self, group: &str, username: Option<&str>) -> bool {
+        match self.groups.get(group) {
+            Some(group) => {
+                group.members.is_empty()
+                    || username
+                        .is_some_and(|u| group.members.iter().any(|m| m.eq_ignore_ascii_case(u)))
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `a` and `b` are both listed members of some group. Open
+    /// groups don't count: everyone is in those.
+    pub fn share_private_group(&self, a: &str, b: &str) -> bool {
+        let listed =
+            |group: &Group, name: &str| group.members.iter().any(|m| m.eq_ignore_ascii_case(name));
+        self.groups
+            .values()
+            .any(|group| listed(group, a) && listed(group, b))
+    }
+
+    /// Drop `username` from every member list. A group it was the last member
+    /// of is removed, since an empty member list would open it to everyone;
+    /// returns the names of those.
+    pub fn remove_member(&mut self, username: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.groups.retain(|name, group| {
+            let before = group.members.len();
+            group.members.retain(|m| !m.eq_ignore_ascii_case(username));
+            let keep = before == 0 || !group.members.is_empty();
+            if !keep {
+                removed.push(name.clone());
+            }
+            keep
+        });
+        removed
+    }
 }
 
-// User representation for API requests
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct User {
-    pub id: usize,
-    pub username: String,
+impl Default for GroupState {
+    fn default() -> Self {
+        let mut groups = HashMap::new();
+        groups.insert(
+            DEFAULT_GROUP.to_string(),
+            Group {
+                name: DEFAULT_GROUP.to_string(),
+                members: Vec::new(),
+                retention: Retention::default(),
+            },
+        );
+        GroupState { groups }
+    }
+}
+
+/// Something a handler asks a live WebSocket connection to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Control {
+    /// Send a close frame and drop the connection.
+    Close { code: u16, reason: &'static str },
+    /// Tell the client a frame of theirs was refused.
+    Error(ErrorBody),
+    /// Tell the client someone's presence changed.
+    Presence(Presence),
+    /// Answer a history request.
+    History(HistoryPage),
+    /// Tell the client someone is typing to it.
+    Typing(Typing),
+    /// Pass on a receipt for messages the client's user sent.
+    Receipt(Receipt),
+}
+
+#[derive(Debug)]
+struct Connection {
+    user_id: Option<String>, // None for anonymous connections
+    control: mpsc::UnboundedSender<Control>,
+}
+
+/// Registry of open WebSockets, so handlers can reach a user's connections,
+/// and the presence that follows from it: a user is online while they have a
+/// connection open.
+#[derive(Debug, Default)]
+pub struct Connections {
+    next_id: u64,
+    open: HashMap<u64, Connection>,
+    away: HashSet<String>,           // User ids
+    last_seen: HashMap<String, u64>, // User id -> when their last connection closed
+}
+
+impl Connections {
+    /// Track a new connection. Returns its id for `unregister` and the
+    /// receiving end of its control channel.
+    pub fn register(&mut self, user_id: Option<String>) -> (u64, mpsc::UnboundedReceiver<Control>) {
+        let (control, rx) = mpsc::unbounded_channel();
+        self.next_id += 1;
+        self.open
+            .insert(self.next_id, Connection { user_id, control });
+        (self.next_id, rx)
+    }
+
+    /// Forget a closed connection. If it was its user's last, they go
+    /// offline and stop being away; returns their id in that case.
+    pub fn unregister(&mut self, id: u64, now: u64) -> Option<String> {
+        let user_id = self.open.remove(&id)?.user_id?;
+        if self.is_online(&user_id) {
+            return None;
+        }
+        self.away.remove(&user_id);
+        self.last_seen.insert(user_id.clone(), now);
+        Some(user_id)
+    }
+
+    pub fn is_online(&self, user_id: &str) -> bool {
+        self.open
+            .values()
+            .any(|c| c.user_id.as_deref() == Some(user_id))
+    }
+
+    /// Mark a user away or back; returns whether that changed anything.
+    pub fn set_away(&mut self, user_id: &str, away: bool) -> bool {
+        if away {
+            self.away.insert(user_id.to_string())
+        } else {
+            self.away.remove(user_id)
+        }
+    }
+
+    /// `user`'s presence as `viewer` (a user id) may see it. Users who hide
+    /// their presence look offline to everyone but themselves.
+    pub fn presence(&self, user: &User, viewer: &str) -> Presence {
+        let hidden = user.hide_presence && user.id != viewer;
+        let status = if hidden || !self.is_online(&user.id) {
+            PresenceStatus::Offline
+        } else if self.away.contains(&user.id) {
+            PresenceStatus::Away
+        } else {
+            PresenceStatus::Online
+        };
+        let last_seen = match status {
+            PresenceStatus::Offline if !hidden => self.last_seen.get(&user.id).copied(),
+            _ => None,
+        };
+        Presence {
+            user_id: user.id.clone(),
+            username: user.username.clone(),
+            status,
+            last_seen,
+        }
+    }
+
+    /// Ids of the users with a connection open, each once.
+    pub fn online_users(&self) -> HashSet<String> {
+        self.open
+            .values()
+            .filter_map(|c| c.user_id.clone())
+            .collect()
+    }
+
+    /// Send `control` to connection `id`, if it is still open.
+    pub fn send(&self, id: u64, control: Control) {
+        if let Some(connection) = self.open.get(&id) {
+            let _ = connection.control.send(control);
+        }
+    }
+
+    /// Send `control` to every connection of `user_id`; returns how many
+    /// there were.
+    pub fn send_to_user(&self, user_id: &str, control: Control) -> usize {
+        self.open
+            .values()
+            .filter(|c| c.user_id.as_deref() == Some(user_id))
+            .filter(|c| c.control.send(control.clone()).is_ok())
+            .count()
+    }
 }
 
-#[derive(Deserialize)]
-pub struct CreateUserPayload {
-    pub username: String,
+/// Milliseconds since the Unix epoch, as in `sent_at` and `last_seen`.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
 }
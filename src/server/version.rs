@@ -0,0 +1,36 @@
+use crate::proto::{API_VERSION, VERSION_HEADER};
+use crate::server::api::ApiError;
+use axum::{
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// API versions this server can answer.
+pub const SUPPORTED_VERSIONS: &[u32] = &[API_VERSION];
+
+/// Middleware: reject requests whose version header asks for a version this
+/// server doesn't speak, and tag every response with the version it used.
+/// Requests without the header get the current version.
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    if let Some(value) = request.headers().get(VERSION_HEADER) {
+        let requested = value.to_str().ok().and_then(|v| v.trim().parse().ok());
+        if !requested.is_some_and(|v| SUPPORTED_VERSIONS.contains(&v)) {
+            return ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "unsupported_version",
+                format!(
+                    "unsupported API version {:?}; this server speaks {:?}",
+                    value, SUPPORTED_VERSIONS
+                ),
+            )
+            .into_response();
+        }
+    }
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(VERSION_HEADER, HeaderValue::from(API_VERSION));
+    response
+}
@@ -1,54 +1,522 @@
-use crate::server::state::AppState;
+use crate::proto::{
+    ChatMessage, ErrorBody, ErrorFrame, HistoryFrame, HistoryRequest, HistoryRequestFrame,
+    PresenceFrame, Receipt, ReceiptFrame, Target, Typing, TypingFrame, User, WS_SUBPROTOCOL,
+};
+use crate::server::api::group::{read_history, validate_group_name};
+use crate::server::api::user::validate_username;
+use crate::server::api::{ApiError, ApiQuery};
+use crate::server::presence::{self, Audience};
+use crate::server::rate_limit::{Limited, Subject};
+use crate::server::state::{now_millis, AppState, Control};
 // src/server/websocket.rs
-use axum::extract::ws::{Utf8Bytes, WebSocket, WebSocketUpgrade};
-use axum::extract::State;
-use axum::response::IntoResponse;
+use axum::body::Bytes;
+use axum::extract::ws::{close_code, CloseFrame, Message, Utf8Bytes, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderMap, StatusCode};
+use axum::response::Response;
 use futures::{sink::SinkExt, stream::StreamExt};
-// Import AppState
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+use utoipa::IntoParams;
 
-pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| websocket(socket, state))
+/// How long a closing connection gets to send its close frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsParams {
+    /// Session token from `POST /login`; omit to connect anonymously
+    pub token: Option<String>,
 }
 
-async fn websocket(socket: WebSocket, state: AppState) {
+/// Open the chat WebSocket. Frames in both directions are JSON
+/// `ChatMessage`s, `TypingFrame`s and `ReceiptFrame`s; the server fills in
+/// `id`, `seq`, `sender` and `sent_at`. Clients may also send a
+/// `HistoryRequestFrame`, which is answered with a `HistoryFrame`.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "websocket",
+    params(WsParams),
+    responses(
+        (status = 101, description = "Switching to the `veil.v1` WebSocket subprotocol"),
+        (status = 400, description = "Invalid query string, or only unsupported subprotocols offered", body = ErrorBody),
+        (status = 401, description = "Unknown session token", body = ErrorBody),
+    )
+)]
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    ApiQuery(params): ApiQuery<WsParams>,
+    State(state): State<AppState>,
+) -> Result<Response, ApiError> {
+    // Clients that offer no subprotocol predate versioning and get v1; ones
+    // that offer only versions we don't speak are turned away
+    let limits = &state.config.websocket;
+    let ws = ws
+        .protocols([WS_SUBPROTOCOL])
+        .max_message_size(limits.max_message_bytes)
+        .max_frame_size(limits.max_frame_bytes);
+    if headers.contains_key(SEC_WEBSOCKET_PROTOCOL) && ws.selected_protocol().is_none() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported_version",
+            format!("this server speaks the {} subprotocol", WS_SUBPROTOCOL),
+        ));
+    }
+    // Anonymous connections are allowed; a token that doesn't resolve is not
+    let user = match params.token {
+        Some(token) => match state.user_state.lock()?.session_user(&token) {
+            Some(user) => Some(user),
+            None => {
+                return Err(ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "unauthorized",
+                    "unknown or expired session token",
+                ))
+            }
+        },
+        None => None,
+    };
+    // Subscribe before the handshake completes, so anything sent once the
+    // client sees the connection open is delivered
+    let rx = state.tx.subscribe();
+    let ip = peer.ip();
+    Ok(ws.on_upgrade(move |socket| websocket(socket, state, user, ip, rx)))
+}
+
+async fn websocket(
+    socket: WebSocket,
+    state: AppState,
+    user: Option<User>,
+    ip: IpAddr,
+    mut rx: broadcast::Receiver<ChatMessage>,
+) {
     let (mut sender, mut receiver) = socket.split();
+    let username = user.as_ref().map(|u| u.username.clone());
+    let user_id = user.map(|u| u.id);
+    let registered = state
+        .connections
+        .lock()
+        .map(|mut connections| {
+            let first = user_id
+                .as_ref()
+                .is_some_and(|id| !connections.is_online(id));
+            (connections.register(user_id.clone()), first)
+        })
+        .ok();
+    let Some(((connection_id, mut control), first)) = registered else {
+        tracing::error!("Connection registry unavailable; dropping WebSocket");
+        return;
+    };
+    if let Some(user_id) = &user_id {
+        presence::introduce(&state, connection_id, user_id);
+        if first {
+            presence::announce(&state, user_id, Audience::Others);
+        }
+    }
+    // Frames count against this connection, its user and its address
+    let mut subjects = vec![Subject::Connection(connection_id), Subject::Ip(ip)];
+    subjects.extend(user_id.clone().map(Subject::User));
 
-    tracing::debug!("New WebSocket connection established");
+    tracing::debug!("New WebSocket connection established for {:?}", username);
 
-    let mut rx = state.tx.subscribe(); // Channel to receive broadcast messages
+    // Unanswered pings; the receive task resets it whenever a pong arrives
+    let missed_pongs = Arc::new(AtomicU32::new(0));
+    let ping_interval = state.config.websocket.ping_interval();
+    let max_missed_pongs = state.config.websocket.max_missed_pongs;
 
     // Spawn a task to handle sending messages to the client
+    let send_state = state.clone();
+    let send_username = username.clone();
+    let send_missed_pongs = missed_pongs.clone();
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if sender
-                .send(axum::extract::ws::Message::Text(Utf8Bytes::from(msg)))
-                .await
-                .is_err()
-            {
-                break; // Connection closed
+        let mut heartbeat = tokio::time::interval(ping_interval);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        heartbeat.reset(); // The first tick would fire at once
+        loop {
+            let message = tokio::select! {
+                chat = rx.recv() => match chat {
+                    Ok(chat) if can_receive(&send_state, &chat, send_username.as_deref()) => {
+                        json_frame(&chat)
+                    }
+                    Ok(_) => continue,
+                    Err(_) => break,
+                },
+                Some(control) = control.recv() => match control {
+                    Control::Close { code, reason } => {
+                        let frame = CloseFrame {
+                            code,
+                            reason: Utf8Bytes::from_static(reason),
+                        };
+                        let _ = sender.send(Message::Close(Some(frame))).await;
+                        break;
+                    }
+                    Control::Error(error) => json_frame(&ErrorFrame { error }),
+                    Control::Presence(presence) => json_frame(&PresenceFrame { presence }),
+                    Control::History(history) => json_frame(&HistoryFrame { history }),
+                    Control::Typing(typing) => json_frame(&TypingFrame { typing }),
+                    Control::Receipt(receipt) => json_frame(&ReceiptFrame { receipt }),
+                },
+                _ = heartbeat.tick() => {
+                    if send_missed_pongs.fetch_add(1, Ordering::Relaxed) >= max_missed_pongs {
+                        tracing::debug!("No pong from {:?}; dropping the connection", send_username);
+                        let frame = CloseFrame {
+                            code: close_code::AWAY,
+                            reason: Utf8Bytes::from_static("ping timeout"),
+                        };
+                        let _ = tokio::time::timeout(CLOSE_TIMEOUT, sender.send(Message::Close(Some(frame)))).await;
+                        break;
+                    }
+                    Message::Ping(Bytes::new())
+                },
+            };
+            // A peer that stopped reading fills the socket buffer and would
+            // block us forever; give up on it after one ping interval
+            match tokio::time::timeout(ping_interval, sender.send(message)).await {
+                Ok(Ok(())) => {}
+                _ => break, // Connection closed or stuck
             }
         }
     });
 
     // Spawn a task to handle receiving messages from the client and broadcasting them
+    let recv_state = state.clone();
+    // Ends with the close frame to send, if the client broke the protocol
+    let recv_user_id = user_id.clone();
     let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
-            if let axum::extract::ws::Message::Text(text) = msg {
-                tracing::debug!("Received message: {:?}", text);
-                let _ = state.tx.send(text.parse().unwrap()); // Broadcast the message
+        let state = recv_state;
+        let user_id = recv_user_id;
+        let mut throttled = false;
+        loop {
+            let text = match receiver.next().await {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Binary(_))) => {
+                    return Some(Control::Close {
+                        code: close_code::UNSUPPORTED,
+                        reason: "binary frames are not supported",
+                    });
+                }
+                Some(Ok(Message::Pong(_))) => {
+                    missed_pongs.store(0, Ordering::Relaxed);
+                    continue;
+                }
+                Some(Ok(_)) => continue, // Pings and close are handled by axum
+                Some(Err(e)) if is_too_big(&e) => {
+                    return Some(Control::Close {
+                        code: close_code::SIZE,
+                        reason: "message too big",
+                    });
+                }
+                Some(Err(_)) | None => return None,
+            };
+            tracing::debug!("Received message: {:?}", text);
+            if !admit(&state, connection_id, &subjects, &mut throttled) {
+                continue;
+            }
+            let mut chat = match serde_json::from_str::<ClientFrame>(text.as_str()) {
+                Ok(ClientFrame::Chat(chat)) => chat,
+                Ok(ClientFrame::Typing(TypingFrame { mut typing })) => {
+                    typing.sender = username.clone();
+                    relay_typing(&state, connection_id, user_id.as_deref(), typing);
+                    continue;
+                }
+                Ok(ClientFrame::History(HistoryRequestFrame { history_request })) => {
+                    let HistoryRequest { group, query } = history_request;
+                    match read_history(&state, &group, username.as_deref(), &query) {
+                        Ok(page) => {
+                            if let Ok(connections) = state.connections.lock() {
+                                connections.send(connection_id, Control::History(page));
+                            }
+                        }
+                        Err(e) => reply_error(&state, connection_id, e.code, e.message, None),
+                    }
+                    continue;
+                }
+                Ok(ClientFrame::Receipt(ReceiptFrame { mut receipt })) => {
+                    receipt.sender = username.clone();
+                    if let Err(message) = relay_receipt(&state, user_id.as_deref(), receipt) {
+                        reply_error(&state, connection_id, "invalid_message", message, None);
+                    }
+                    continue;
+                }
+                Err(_) => {
+                    return Some(Control::Close {
+                        code: close_code::POLICY,
+                        reason: "malformed frame",
+                    });
+                }
+            };
+            if let Err(message) = validate_message(&chat) {
+                reply_error(&state, connection_id, "invalid_message", message, None);
+                continue;
             }
+            // Never trust the client's claims
+            chat.id = Some(uuid::Uuid::new_v4().simple().to_string());
+            chat.sender = username.clone();
+            chat.sent_at = Some(now_millis());
+            if let Err((code, message)) = can_post(&state, &mut chat.target, chat.sender.as_deref())
+            {
+                tracing::debug!("Dropping message to {} from {:?}", chat.target, username);
+                reply_error(&state, connection_id, code, message, None);
+                continue;
+            }
+            let Target::Group(group) = chat.target.clone() else {
+                let _ = state.tx.send(chat); // Direct messages aren't stored
+                continue;
+            };
+            // Number and broadcast under one lock, so every client sees
+            // group messages in sequence order
+            let Ok(mut history) = state.history.lock() else {
+                continue;
+            };
+            history.append(&group, &mut chat);
+            let _ = state.tx.send(chat);
         }
     });
 
-    // Wait for either task to complete (connection closed)
+    // Wait for either task to complete (connection closed), then stop the
+    // other so a closed connection can no longer post
     tokio::select! {
         _ = (&mut send_task) => {
             tracing::debug!("Send task finished");
+            recv_task.abort();
         },
-        _ = (&mut recv_task) => {
+        close = (&mut recv_task) => {
             tracing::debug!("Receive task finished");
+            // Let the send task say why before it goes
+            if let Ok(Some(close)) = close {
+                tracing::debug!("Closing WebSocket: {:?}", close);
+                if let Ok(connections) = state.connections.lock() {
+                    connections.send(connection_id, close);
+                }
+                let _ = tokio::time::timeout(CLOSE_TIMEOUT, &mut send_task).await;
+            }
+            send_task.abort();
         },
     };
+    let offline = state
+        .connections
+        .lock()
+        .ok()
+        .and_then(|mut connections| connections.unregister(connection_id, now_millis()));
+    if let Some(user_id) = offline {
+        presence::announce(&state, &user_id, Audience::Others);
+    }
+    if let Ok(mut limiter) = state.limiter.lock() {
+        limiter.forget_connection(connection_id);
+    }
 
     tracing::debug!("WebSocket connection closed");
 }
+
+// Rate-limit incoming frames. The first refused frame in a row gets an error
+// frame back; later ones are dropped silently, so a flood in doesn't turn
+// into one out.
+fn admit(state: &AppState, connection_id: u64, subjects: &[Subject], throttled: &mut bool) -> bool {
+    let Ok(verdict) = state
+        .limiter
+        .lock()
+        .map(|mut limiter| limiter.check(Limited::Frame, subjects))
+    else {
+        return false;
+    };
+    let Err(wait) = verdict else {
+        *throttled = false;
+        return true;
+    };
+    if !std::mem::replace(throttled, true) {
+        tracing::debug!("Throttling connection {}", connection_id);
+        reply_error(
+            state,
+            connection_id,
+            "rate_limited",
+            "sending too fast; frames are being dropped".to_string(),
+            Some(wait.as_millis() as u64),
+        );
+    }
+    false
+}
+
+fn reply_error(
+    state: &AppState,
+    connection_id: u64,
+    code: &str,
+    message: String,
+    retry_after_ms: Option<u64>,
+) {
+    let error = ErrorBody {
+        code: code.to_string(),
+        message,
+        request_id: None,
+        retry_after_ms,
+    };
+    if let Ok(connections) = state.connections.lock() {
+        connections.send(connection_id, Control::Error(error));
+    }
+}
+
+/// Anything a client may send, told apart by its fields.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClientFrame {
+    History(HistoryRequestFrame),
+    Typing(TypingFrame),
+    Receipt(ReceiptFrame),
+    Chat(ChatMessage),
+}
+
+// Typing frames go to whoever could read a message to the same target, except
+// the typist. They are never stored, and ones over the limit are dropped
+// without a word: they are sent automatically, so an error would only be noise.
+fn relay_typing(state: &AppState, connection_id: u64, user_id: Option<&str>, mut typing: Typing) {
+    let Some(user_id) = user_id else {
+        return;
+    };
+    let valid = match &typing.target {
+        Target::Group(group) => validate_group_name(group).is_ok(),
+        Target::Direct(recipient) => validate_username(recipient).is_ok(),
+    };
+    if !valid || can_post(state, &mut typing.target, typing.sender.as_deref()).is_err() {
+        return;
+    }
+    let allowed = state.limiter.lock().is_ok_and(|mut limiter| {
+        limiter
+            .check(Limited::Typing, &[Subject::Connection(connection_id)])
+            .is_ok()
+    });
+    if !allowed {
+        return;
+    }
+    let Ok(users) = state.user_state.lock() else {
+        return;
+    };
+    let (Ok(groups), Ok(connections)) = (state.group_state.lock(), state.connections.lock()) else {
+        return;
+    };
+    for reader_id in connections.online_users() {
+        let Some(reader) = users.users.get(&reader_id) else {
+            continue;
+        };
+        let reads = match &typing.target {
+            Target::Group(group) => groups.is_member(group, Some(&reader.username)),
+            Target::Direct(recipient) => reader.username.eq_ignore_ascii_case(recipient),
+        };
+        if reads && reader_id != user_id {
+            connections.send_to_user(&reader_id, Control::Typing(typing.clone()));
+        }
+    }
+}
+
+// Receipts go to the one user whose messages they are about. Their payload
+// is passed on unread, though clients send it as plain JSON for now. Users
+// who turned receipts off have theirs dropped, in case a client ignores that.
+fn relay_receipt(state: &AppState, user_id: Option<&str>, receipt: Receipt) -> Result<(), String> {
+    let Some(user_id) = user_id else {
+        return Err("log in to send receipts".to_string());
+    };
+    validate_username(&receipt.to)?;
+    if receipt.payload.is_empty() {
+        return Err("payload must not be empty".to_string());
+    }
+    let (hidden, recipient) = {
+        let users = state.user_state.lock().map_err(|e| e.to_string())?;
+        let hidden = users.users.get(user_id).is_none_or(|u| u.hide_receipts);
+        let recipient = users.find_by_username(&receipt.to).map(|u| u.id.clone());
+        (hidden, recipient)
+    };
+    let recipient = recipient.ok_or_else(|| format!("no user named '{}'", receipt.to))?;
+    if hidden {
+        return Ok(());
+    }
+    if let Ok(connections) = state.connections.lock() {
+        connections.send_to_user(&recipient, Control::Receipt(receipt));
+    }
+    Ok(())
+}
+
+fn json_frame<T: Serialize>(value: &T) -> Message {
+    Message::Text(Utf8Bytes::from(serde_json::to_string(value).unwrap()))
+}
+
+/// Check the fields of a message from a client. `id`, `sender` and `sent_at`
+/// are not checked, since the server overwrites them.
+fn validate_message(chat: &ChatMessage) -> Result<(), String> {
+    match &chat.target {
+        Target::Group(group) => validate_group_name(group)?,
+        Target::Direct(recipient) => validate_username(recipient)?,
+    }
+    if chat.body.is_empty() {
+        return Err("body must not be empty".to_string());
+    }
+    Ok(())
+}
+
+// Size limits are enforced by tungstenite, which axum wraps in its own error
+fn is_too_big(e: &axum::Error) -> bool {
+    e.source()
+        .and_then(|source| source.downcast_ref::<tungstenite::Error>())
+        .is_some_and(|e| matches!(e, tungstenite::Error::Capacity(_)))
+}
+
+// Group messages need membership (or an open group); direct messages need a
+// logged-in sender and an existing recipient, whose name is rewritten as they
+// registered it so both sides file the conversation alike. Both checks fail
+// closed if the state is unavailable.
+fn can_post(
+    state: &AppState,
+    target: &mut Target,
+    sender: Option<&str>,
+) -> Result<(), (&'static str, String)> {
+    match target {
+        Target::Group(group) => {
+            let member = state
+                .group_state
+                .lock()
+                .is_ok_and(|groups| groups.is_member(group, sender));
+            if !member {
+                return Err(("forbidden", format!("you can't post to {}", group)));
+            }
+        }
+        Target::Direct(recipient) => {
+            if sender.is_none() {
+                return Err(("forbidden", "log in to send direct messages".to_string()));
+            }
+            let registered = state.user_state.lock().ok().and_then(|users| {
+                users
+                    .find_by_username(recipient)
+                    .map(|u| u.username.clone())
+            });
+            let Some(registered) = registered else {
+                return Err(("unknown_recipient", format!("no user named {}", recipient)));
+            };
+            *recipient = registered;
+        }
+    }
+    Ok(())
+}
+
+// Direct messages go to both parties only, so the sender sees its own copy
+fn can_receive(state: &AppState, chat: &ChatMessage, username: Option<&str>) -> bool {
+    match &chat.target {
+        Target::Group(group) => state
+            .group_state
+            .lock()
+            .is_ok_and(|groups| groups.is_member(group, username)),
+        Target::Direct(recipient) => {
+            let is = |name: Option<&str>| {
+                username
+                    .zip(name)
+                    .is_some_and(|(u, n)| u.eq_ignore_ascii_case(n))
+            };
+            is(Some(recipient)) || is(chat.sender.as_deref())
+        }
+    }
+}
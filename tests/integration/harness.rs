@@ -0,0 +1,163 @@
+use futures::StreamExt;
+use project_veil::proto::{ChatMessage, Presence};
+use project_veil::sdk::{Event, EventStream, VeilClient};
+use project_veil::server::{start_server, RunningServer, ServerConfig};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::{timeout, timeout_at, Instant};
+
+// --- In-process server harness ---
+//
+// Each test gets its own server on a free port with fresh state, and
+// connects as many clients as it needs through the public SDK.
+
+/// How long to wait for a message that should arrive.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait before concluding a message will not arrive.
+const SILENCE: Duration = Duration::from_millis(200);
+/// Every test account's password, including admins from `with_admin`.
+pub const PASSWORD: &str = "correct horse";
+
+pub struct TestServer {
+    server: RunningServer,
+}
+
+impl TestServer {
+    pub async fn start() -> TestServer {
+        Self::start_with(ServerConfig::default()).await
+    }
+
+    pub async fn start_with(config: ServerConfig) -> TestServer {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = start_server(addr, config).await.expect("start server");
+        TestServer { server }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    /// A client that has not logged in or connected.
+    pub fn client(&self) -> VeilClient {
+        VeilClient::new(self.server.url())
+    }
+
+    /// Register `username`, log in and open a WebSocket.
+    pub async fn connect_as(&self, username: &str) -> Peer {
+        let client = self.client();
+        client
+            .create_user(username, PASSWORD)
+            .await
+            .expect("create user");
+        self.connect_existing(username).await
+    }
+
+    /// Log in as an account that already exists, such as an admin from the
+    /// config, and open a WebSocket.
+    pub async fn connect_existing(&self, username: &str) -> Peer {
+        let mut client = self.client();
+        client.login(username, PASSWORD).await.expect("log in");
+        Peer::connect(client).await
+    }
+
+    pub async fn connect_anonymous(&self) -> Peer {
+        Peer::connect(self.client()).await
+    }
+
+    pub async fn shutdown(self) {
+        self.server.shutdown().await.expect("clean shutdown");
+    }
+}
+
+/// A connected client and its incoming events.
+pub struct Peer {
+    pub client: VeilClient,
+    events: EventStream,
+}
+
+impl Peer {
+    pub async fn connect(mut client: VeilClient) -> Peer {
+        let events = client.connect().await.expect("connect WebSocket");
+        Peer { client, events }
+    }
+
+    /// The first event `pick` accepts, skipping the others. Fails the test
+    /// unless one arrives within the delivery timeout.
+    async fn next_matching<T>(&mut self, mut pick: impl FnMut(Event) -> Option<T>) -> T {
+        let deadline = tokio::time::Instant::now() + DELIVERY_TIMEOUT;
+        loop {
+            let event = timeout_at(deadline, self.events.next())
+                .await
+                .expect("event within timeout")
+                .expect("connection open")
+                .expect("valid event");
+            if let Some(picked) = pick(event) {
+                return picked;
+            }
+        }
+    }
+
+    /// The next event other than presence and heartbeat updates, which
+    /// arrive whenever peers come and go.
+    pub async fn next_event(&mut self) -> Event {
+        self.next_matching(|event| (!is_background(&event)).then_some(event))
+            .await
+    }
+
+    /// The next chat message, failing the test if none arrives in time.
+    pub async fn next_message(&mut self) -> ChatMessage {
+        self.next_matching(|event| match event {
+            Event::Message(chat) => Some(chat),
+            _ => None,
+        })
+        .await
+    }
+
+    /// The next presence frame about `username`, skipping anything else.
+    pub async fn next_presence_of(&mut self, username: &str) -> Presence {
+        self.next_matching(|event| match event {
+            Event::Presence(presence) if presence.username == username => Some(presence),
+            _ => None,
+        })
+        .await
+    }
+
+    /// The next heartbeat round trip, skipping anything else.
+    pub async fn next_latency(&mut self) -> Duration {
+        self.next_matching(|event| match event {
+            Event::Latency(rtt) => Some(rtt),
+            _ => None,
+        })
+        .await
+    }
+
+    /// The close code the server ends the connection with, skipping any
+    /// messages still in flight.
+    pub async fn next_close_code(&mut self) -> u16 {
+        loop {
+            let event = timeout(DELIVERY_TIMEOUT, self.events.next())
+                .await
+                .expect("close within timeout")
+                .expect("close frame before the stream ends")
+                .expect("valid event");
+            if let Event::Closed { code, .. } = event {
+                return code;
+            }
+        }
+    }
+
+    /// Fail if anything but presence and heartbeat updates arrives within a
+    /// short grace period.
+    pub async fn assert_no_message(&mut self) {
+        let deadline = Instant::now() + SILENCE;
+        while let Ok(Some(event)) = timeout_at(deadline, self.events.next()).await {
+            if !event.as_ref().is_ok_and(is_background) {
+                panic!("expected no message, got {:?}", event);
+            }
+        }
+    }
+}
+
+fn is_background(event: &Event) -> bool {
+    matches!(event, Event::Presence(_) | Event::Latency(_))
+}
@@ -0,0 +1,1376 @@
+#![cfg(all(feature = "server", feature = "sdk"))]
+
+mod harness;
+
+use harness::{Peer, TestServer, PASSWORD};
+use project_veil::proto::{
+    ErrorBody, PresenceStatus, Role, Target, CLOSE_ACCOUNT_DELETED, DEFAULT_GROUP, VERSION_HEADER,
+    WS_SUBPROTOCOL,
+};
+use project_veil::sdk::{decode_payload, encode_payload, Attachment, MessagePayload};
+use project_veil::sdk::{
+    ClientError, HistoryPage, HistoryQuery, LoginResponse, ReceiptBody, ReceiptStatus, Retention,
+    UpdateUserPayload,
+};
+use project_veil::sdk::{Event, Heartbeat};
+use project_veil::server::config::{
+    AdminAccount, BlobConfig, HistoryConfig, Rate, RateLimits, WebSocketConfig,
+};
+use project_veil::server::password::PasswordHash;
+use project_veil::server::ServerConfig;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+#[tokio::test]
+async fn group_message_reaches_every_member() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let mut guest = server.connect_anonymous().await;
+
+    alice.client.send(DEFAULT_GROUP, "hello").await.unwrap();
+
+    for peer in [&mut alice, &mut bob, &mut guest] {
+        let chat = peer.next_message().await;
+        assert_eq!(chat.target, Target::Group(DEFAULT_GROUP.to_string()));
+        assert_eq!(chat.sender.as_deref(), Some("alice"));
+        assert_eq!(chat.body, "hello");
+        assert!(chat.sent_at.is_some());
+    }
+}
+
+#[tokio::test]
+async fn anonymous_messages_carry_no_sender() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let guest = server.connect_anonymous().await;
+
+    guest.client.send(DEFAULT_GROUP, "psst").await.unwrap();
+
+    assert_eq!(alice.next_message().await.sender, None);
+}
+
+#[tokio::test]
+async fn invalid_token_is_rejected() {
+    let server = TestServer::start().await;
+    let url = format!("ws://{}/ws?token=not-a-session", server.addr());
+
+    match tokio_tungstenite::connect_async(url).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), 401)
+        }
+        other => panic!("expected 401, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn login_needs_the_right_password() {
+    let server = TestServer::start().await;
+    let mut client = server.client();
+    client.create_user("alice", PASSWORD).await.unwrap();
+
+    // Unknown users and wrong passwords look the same
+    for (username, password) in [("nobody", PASSWORD), ("alice", "incorrect horse")] {
+        match client.login(username, password).await {
+            Err(ClientError::Api { status, code, .. }) => {
+                assert_eq!(status, 401);
+                assert_eq!(code, "invalid_credentials");
+            }
+            other => panic!("expected 401, got {:?}", other.map(|_| ())),
+        }
+    }
+    assert_eq!(
+        client.login("ALICE", PASSWORD).await.unwrap().username,
+        "alice"
+    );
+
+    match client.create_user("bob", "short").await {
+        Err(ClientError::Api { status, code, .. }) => {
+            assert_eq!(status, 422);
+            assert_eq!(code, "invalid_password");
+        }
+        other => panic!("expected 422, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn private_group_is_limited_to_members() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let mut carol = server.connect_as("carol").await;
+    let members = ["alice".to_string(), "bob".to_string()];
+    alice.client.create_group("ops", &members).await.unwrap();
+
+    // Posts from outsiders are refused
+    carol.client.send("ops", "let me in").await.unwrap();
+    match carol.next_event().await {
+        Event::Error(error) => assert_eq!(error.code, "forbidden"),
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+    alice.client.send("ops", "members only").await.unwrap();
+
+    for peer in [&mut alice, &mut bob] {
+        assert_eq!(peer.next_message().await.body, "members only");
+    }
+    carol.assert_no_message().await;
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn headless_send_fails_when_the_message_is_refused() {
+    use project_veil::client::headless;
+
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    server.connect_as("bob").await;
+    let members = ["alice".to_string(), "bob".to_string()];
+    alice.client.create_group("ops", &members).await.unwrap();
+    let url = format!("http://{}", server.addr());
+
+    let sent = headless::send(&url, DEFAULT_GROUP, None, "hello".to_string()).await;
+    assert!(sent.is_ok(), "{:?}", sent);
+    assert_eq!(alice.next_message().await.body, "hello");
+
+    let sent = headless::send(&url, "ops", None, "let me in".to_string()).await;
+    let error = sent
+        .expect_err("anonymous post to a private group")
+        .to_string();
+    assert!(error.contains("can't post to ops"), "{}", error);
+    alice.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn groups_need_a_login_and_registered_members() {
+    let server = TestServer::start().await;
+    let alice = server.connect_as("alice").await;
+    server.connect_as("Bob").await;
+    let code = |result: Result<_, ClientError>| match result {
+        Err(ClientError::Api { status, code, .. }) => (status.as_u16(), code),
+        other => panic!("expected an API error, got {:?}", other.map(|_| ())),
+    };
+
+    let anonymous = server.client();
+    let members = ["alice".to_string()];
+    assert_eq!(
+        code(anonymous.create_group("ops", &members).await),
+        (401, "unauthorized".to_string())
+    );
+    // A name nobody holds yet can't reserve a place for its future owner
+    let members = ["alice".to_string(), "mallory".to_string()];
+    assert_eq!(
+        code(alice.client.create_group("ops", &members).await),
+        (422, "unknown_member".to_string())
+    );
+    let members = ["alice".to_string(), "bad name".to_string()];
+    assert_eq!(
+        code(alice.client.create_group("ops", &members).await),
+        (422, "invalid_member".to_string())
+    );
+
+    // Members are stored as registered, once each
+    let members = ["ALICE".to_string(), "bob".to_string(), "alice".to_string()];
+    let group = alice.client.create_group("ops", &members).await.unwrap();
+    assert_eq!(group.members, ["alice", "Bob"]);
+}
+
+#[tokio::test]
+async fn usernames_match_regardless_of_case() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("Alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let members = ["alice".to_string(), "BOB".to_string()];
+    bob.client.create_group("ops", &members).await.unwrap();
+
+    alice.client.send("ops", "in here").await.unwrap();
+    for peer in [&mut alice, &mut bob] {
+        assert_eq!(peer.next_message().await.body, "in here");
+    }
+
+    // Direct messages arrive addressed to the name as registered
+    bob.client.send_direct("alice", "psst").await.unwrap();
+    for peer in [&mut alice, &mut bob] {
+        let chat = peer.next_message().await;
+        assert_eq!(chat.target, Target::Direct("Alice".to_string()));
+        assert_eq!(chat.body, "psst");
+    }
+}
+
+#[tokio::test]
+async fn direct_messages_reach_only_both_parties() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let mut carol = server.connect_as("carol").await;
+
+    alice.client.send_direct("bob", "just us").await.unwrap();
+
+    for peer in [&mut alice, &mut bob] {
+        let chat = peer.next_message().await;
+        assert_eq!(chat.target, Target::Direct("bob".to_string()));
+        assert_eq!(chat.sender.as_deref(), Some("alice"));
+    }
+    carol.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn direct_messages_need_a_logged_in_sender() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let mut guest = server.connect_anonymous().await;
+
+    guest.client.send_direct("alice", "who am I").await.unwrap();
+    match guest.next_event().await {
+        Event::Error(error) => assert_eq!(error.code, "forbidden"),
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+    alice.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn direct_messages_to_unknown_users_are_refused() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+
+    alice.client.send_direct("nobody", "hello?").await.unwrap();
+    match alice.next_event().await {
+        Event::Error(error) => assert_eq!(error.code, "unknown_recipient"),
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn shutdown_stops_the_server() {
+    let server = TestServer::start().await;
+    let client = server.client();
+    client.list_users().await.unwrap();
+
+    server.shutdown().await;
+
+    assert!(client.list_users().await.is_err());
+}
+
+#[tokio::test]
+async fn usernames_are_unique_regardless_of_case() {
+    let server = TestServer::start().await;
+    let client = server.client();
+    let alice = client.create_user("alice", PASSWORD).await.unwrap();
+    let bob = client.create_user("bob", PASSWORD).await.unwrap();
+    assert_ne!(alice.id, bob.id);
+
+    match client.create_user("Alice", PASSWORD).await {
+        Err(ClientError::Api { status, code, .. }) => {
+            assert_eq!(status, 409);
+            assert_eq!(code, "username_taken");
+        }
+        other => panic!("expected 409, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn invalid_usernames_are_rejected() {
+    let server = TestServer::start().await;
+    let client = server.client();
+
+    for name in ["", "-dash", "has space", "ünïcode", &"x".repeat(33)] {
+        match client.create_user(name, PASSWORD).await {
+            Err(ClientError::Api { status, code, .. }) => {
+                assert_eq!(status, 422, "{:?}", name);
+                assert_eq!(code, "invalid_username");
+            }
+            other => panic!("expected 422 for {:?}, got {:?}", name, other.map(|_| ())),
+        }
+    }
+}
+
+#[tokio::test]
+async fn only_the_owner_can_edit_a_profile() {
+    let server = TestServer::start().await;
+    let mut alice = server.client();
+    let alice_id = alice.create_user("alice", PASSWORD).await.unwrap().id;
+    alice.login("alice", PASSWORD).await.unwrap();
+
+    let update = UpdateUserPayload {
+        display_name: Some("Alice Liddell".to_string()),
+        status_text: Some("down the rabbit hole".to_string()),
+        ..Default::default()
+    };
+    let user = alice.update_profile(&update).await.unwrap();
+    assert_eq!(user.display_name.as_deref(), Some("Alice Liddell"));
+
+    // An empty string clears a field; others stay as they were
+    let clear = UpdateUserPayload {
+        status_text: Some(String::new()),
+        ..Default::default()
+    };
+    let user = alice.update_profile(&clear).await.unwrap();
+    assert_eq!(user.status_text, None);
+    assert_eq!(user.display_name.as_deref(), Some("Alice Liddell"));
+
+    let bad = UpdateUserPayload {
+        avatar_hash: Some("not-a-hash".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        alice.update_profile(&bad).await,
+        Err(ClientError::Api { status, .. }) if status == 422
+    ));
+
+    // Someone else's session, and no session at all
+    let url = format!("http://{}/users/{}", server.addr(), alice_id);
+    server
+        .client()
+        .create_user("mallory", PASSWORD)
+        .await
+        .unwrap();
+    let http = reqwest::Client::new();
+    let token = login_token(&server, "mallory").await;
+    let forbidden = http.patch(&url).bearer_auth(token).json(&update).send();
+    assert_eq!(forbidden.await.unwrap().status(), 403);
+    let anonymous = http.patch(&url).json(&update).send();
+    assert_eq!(anonymous.await.unwrap().status(), 401);
+}
+
+#[tokio::test]
+async fn api_versions_are_negotiated() {
+    let server = TestServer::start().await;
+    let http = reqwest::Client::new();
+
+    // Versioned and pre-versioning paths answer alike, tagged with the version
+    for path in ["/v1/users", "/users"] {
+        let url = format!("http://{}{}", server.addr(), path);
+        let response = http.get(url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()[VERSION_HEADER], "1");
+    }
+
+    let url = format!("http://{}/v1/users", server.addr());
+    let response = http.get(url).header(VERSION_HEADER, "2").send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.code, "unsupported_version");
+}
+
+#[tokio::test]
+async fn websocket_subprotocol_is_negotiated() {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let server = TestServer::start().await;
+    let handshake = |protocol: &'static str| {
+        let url = format!("ws://{}/v1/ws", server.addr());
+        let mut request = url.into_client_request().unwrap();
+        let header = "Sec-WebSocket-Protocol";
+        request
+            .headers_mut()
+            .insert(header, protocol.parse().unwrap());
+        tokio_tungstenite::connect_async(request)
+    };
+
+    let (_socket, response) = handshake(WS_SUBPROTOCOL).await.unwrap();
+    assert_eq!(response.headers()["sec-websocket-protocol"], WS_SUBPROTOCOL);
+    match handshake("veil.v2").await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), 400)
+        }
+        other => panic!("expected 400, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn openapi_document_covers_the_api() {
+    let server = TestServer::start().await;
+    let url = format!("http://{}/openapi.json", server.addr());
+    let doc: serde_json::Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+
+    assert_eq!(doc["openapi"].as_str().unwrap().chars().next(), Some('3'));
+    let paths = doc["paths"].as_object().unwrap();
+    for path in [
+        "/v1/users",
+        "/v1/users/{id}",
+        "/v1/login",
+        "/v1/groups",
+        "/v1/blobs",
+        "/v1/blobs/{hash}",
+        "/v1/ws",
+    ] {
+        assert!(paths.contains_key(path), "{} missing", path);
+    }
+    assert!(paths["/v1/users/{id}"]["delete"]["security"].is_array());
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+    for schema in [
+        "User",
+        "CreateUserPayload",
+        "ChatMessage",
+        "Target",
+        "ErrorBody",
+        "BlobInfo",
+    ] {
+        assert!(schemas.contains_key(schema), "{} missing", schema);
+    }
+}
+
+#[tokio::test]
+async fn errors_are_json_with_request_ids() {
+    let server = TestServer::start().await;
+    let http = reqwest::Client::new();
+    let base = format!("http://{}/v1", server.addr());
+
+    // Malformed bodies and unknown routes get the same shape as handler errors
+    let response = http
+        .post(format!("{}/users", base))
+        .header("content-type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let header_id = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.code, "invalid_body");
+    assert_eq!(body.request_id, Some(header_id));
+
+    // So do query strings that don't parse
+    for query in ["limit=abc", "before=-1"] {
+        let url = format!("{}/groups/{}/messages?{}", base, DEFAULT_GROUP, query);
+        let response = http.get(url).send().await.unwrap();
+        assert_eq!(response.status(), 400);
+        let body: ErrorBody = response.json().await.unwrap();
+        assert_eq!(body.code, "invalid_query");
+        assert!(body.request_id.is_some());
+    }
+
+    let response = http.get(format!("{}/nowhere", base)).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(
+        response.json::<ErrorBody>().await.unwrap().code,
+        "not_found"
+    );
+
+    // A caller-supplied ID is kept, so it can be traced through proxies
+    let response = http
+        .get(format!("{}/users", base))
+        .header("x-request-id", "trace-42")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "trace-42");
+
+    // The SDK passes the details on
+    match server.client().login("nobody", PASSWORD).await {
+        Err(ClientError::Api {
+            code,
+            message,
+            request_id,
+            ..
+        }) => {
+            assert_eq!(code, "invalid_credentials");
+            assert_eq!(message, "wrong username or password");
+            assert!(request_id.is_some());
+        }
+        other => panic!("expected an API error, got {:?}", other.map(|_| ())),
+    }
+}
+
+fn with_admin(username: &str) -> ServerConfig {
+    ServerConfig {
+        admins: vec![AdminAccount {
+            username: username.to_string(),
+            password_hash: PasswordHash::new(PASSWORD),
+        }],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn only_owners_and_admins_can_delete_accounts() {
+    let server = TestServer::start_with(with_admin("root")).await;
+    let mut alice = server.client();
+    let alice_id = alice.create_user("alice", PASSWORD).await.unwrap().id;
+    alice.login("alice", PASSWORD).await.unwrap();
+    let mut mallory = server.client();
+    let mallory_id = mallory.create_user("mallory", PASSWORD).await.unwrap().id;
+    mallory.login("mallory", PASSWORD).await.unwrap();
+
+    let status = |result| match result {
+        Err(ClientError::Api { status, .. }) => status.as_u16(),
+        other => panic!("expected an API error, got {:?}", other),
+    };
+    assert_eq!(status(mallory.delete_user(&alice_id).await), 403);
+    assert_eq!(status(server.client().delete_user(&alice_id).await), 401);
+
+    // Owners may delete themselves, admins anyone
+    mallory.delete_user(&mallory_id).await.unwrap();
+    let mut root = server.client();
+    assert_eq!(
+        root.login("root", PASSWORD).await.unwrap().role,
+        Role::Admin
+    );
+    root.delete_user(&alice_id).await.unwrap();
+
+    let users = root.list_users().await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, "root");
+    // Sessions go with the account
+    assert_eq!(status(alice.delete_user(&alice_id).await), 401);
+
+    // Admin names stay reserved, even once the account is gone
+    let root_id = root.user().unwrap().id.clone();
+    root.delete_user(&root_id).await.unwrap();
+    for name in ["root", "ROOT"] {
+        assert_eq!(
+            status(
+                server
+                    .client()
+                    .create_user(name, PASSWORD)
+                    .await
+                    .map(|_| ())
+            ),
+            409
+        );
+    }
+}
+
+#[tokio::test]
+async fn deleting_a_user_cleans_up_after_them() {
+    let server = TestServer::start_with(with_admin("root")).await;
+    let mut alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let root = server.connect_existing("root").await;
+    let pair = ["alice".to_string(), "bob".to_string()];
+    root.client.create_group("pair", &pair).await.unwrap();
+    root.client
+        .create_group("solo", &["alice".to_string()])
+        .await
+        .unwrap();
+
+    let alice_id = alice.client.user().unwrap().id.clone();
+    root.client.delete_user(&alice_id).await.unwrap();
+    assert_eq!(alice.next_close_code().await, CLOSE_ACCOUNT_DELETED);
+
+    // Bob keeps the group to himself; the group only Alice was in is gone
+    // rather than left open to everyone
+    let groups = bob.client.list_groups().await.unwrap();
+    let pair = groups.iter().find(|g| g.name == "pair").unwrap();
+    assert_eq!(pair.members, ["bob"]);
+    assert!(groups.iter().all(|g| g.name != "solo"));
+
+    // Bob's connection is untouched
+    bob.client.send("pair", "still here").await.unwrap();
+    assert_eq!(bob.next_message().await.body, "still here");
+}
+
+fn with_limits(limits: RateLimits) -> ServerConfig {
+    ServerConfig {
+        rate_limits: limits,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn login_attempts_are_rate_limited() {
+    let limits = RateLimits {
+        logins_per_ip: Rate::new(2, 1),
+        ..Default::default()
+    };
+    let server = TestServer::start_with(with_limits(limits)).await;
+    server
+        .client()
+        .create_user("alice", PASSWORD)
+        .await
+        .unwrap();
+
+    login_token(&server, "alice").await;
+    login_token(&server, "alice").await;
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/v1/login", server.addr()))
+        .json(&serde_json::json!({ "username": "alice", "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.code, "rate_limited");
+    assert!(body.retry_after_ms.is_some());
+}
+
+#[tokio::test]
+async fn login_attempts_are_limited_per_account_across_addresses() {
+    let limits = RateLimits {
+        logins_per_account: Rate::new(2, 1),
+        ..Default::default()
+    };
+    let server = TestServer::start_with(with_limits(limits)).await;
+    server
+        .client()
+        .create_user("alice", PASSWORD)
+        .await
+        .unwrap();
+
+    let login_from = |ip: [u8; 4], username: &'static str| {
+        let url = format!("http://{}/v1/login", server.addr());
+        async move {
+            reqwest::Client::builder()
+                .local_address(std::net::IpAddr::from(ip))
+                .build()
+                .unwrap()
+                .post(url)
+                .json(&serde_json::json!({ "username": username, "password": "wrong guess" }))
+                .send()
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+    assert_eq!(login_from([127, 0, 0, 1], "alice").await, 401);
+    assert_eq!(login_from([127, 0, 0, 2], "Alice").await, 401);
+    assert_eq!(login_from([127, 0, 0, 3], "alice").await, 429);
+    // Other accounts are unaffected, and unknown names are limited the same way
+    assert_eq!(login_from([127, 0, 0, 3], "nobody").await, 401);
+    assert_eq!(login_from([127, 0, 0, 4], "nobody").await, 401);
+    assert_eq!(login_from([127, 0, 0, 5], "nobody").await, 429);
+}
+
+#[tokio::test]
+async fn flooding_a_socket_gets_an_error_frame() {
+    let limits = RateLimits {
+        frames_per_connection: Rate::new(3, 1),
+        ..Default::default()
+    };
+    let config = ServerConfig {
+        rate_limits: limits,
+        ..with_admin("root")
+    };
+    let server = TestServer::start_with(config).await;
+    let mut alice = server.connect_as("alice").await;
+
+    for i in 0..6 {
+        alice
+            .client
+            .send(DEFAULT_GROUP, &format!("spam {}", i))
+            .await
+            .unwrap();
+    }
+    // Three get through and one error frame reports the rest, in any order
+    let (mut delivered, mut errors) = (0, Vec::new());
+    while delivered + errors.len() < 4 {
+        match alice.next_event().await {
+            Event::Message(_) => delivered += 1,
+            Event::Error(error) => errors.push(error),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    assert_eq!(delivered, 3);
+    assert_eq!(errors[0].code, "rate_limited");
+    assert!(errors[0].retry_after_ms.is_some());
+    alice.assert_no_message().await;
+
+    // Metrics are for admins only
+    let url = format!("http://{}/metrics", server.addr());
+    let http = reqwest::Client::new();
+    assert_eq!(http.get(&url).send().await.unwrap().status(), 401);
+    let token = login_token(&server, "alice").await;
+    let response = http.get(&url).bearer_auth(token).send().await.unwrap();
+    assert_eq!(response.status(), 403);
+    let token = login_token(&server, "root").await;
+    let response = http.get(&url).bearer_auth(token).send().await.unwrap();
+    let metrics = response.text().await.unwrap();
+    assert!(metrics.contains(r#"veil_rate_limited_total{action="frame",scope="connection"} 3"#));
+}
+
+#[tokio::test]
+async fn oversized_messages_close_the_socket() {
+    let config = ServerConfig {
+        websocket: WebSocketConfig {
+            max_message_bytes: 1024,
+            max_frame_bytes: 1024,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server = TestServer::start_with(config).await;
+    let mut alice = server.connect_as("alice").await;
+
+    alice
+        .client
+        .send(DEFAULT_GROUP, &"x".repeat(900))
+        .await
+        .unwrap();
+    assert_eq!(alice.next_message().await.body.len(), 900);
+    alice
+        .client
+        .send(DEFAULT_GROUP, &"x".repeat(2000))
+        .await
+        .unwrap();
+    assert_eq!(alice.next_close_code().await, 1009);
+}
+
+#[tokio::test]
+async fn protocol_violations_close_the_socket() {
+    let server = TestServer::start().await;
+    assert_eq!(
+        raw_close_code(&server, WsMessage::Binary(vec![1, 2, 3].into())).await,
+        1003
+    );
+    assert_eq!(
+        raw_close_code(&server, WsMessage::Text("not json".into())).await,
+        1008
+    );
+}
+
+#[tokio::test]
+async fn invalid_messages_are_refused() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+
+    alice.client.send(DEFAULT_GROUP, "").await.unwrap();
+    alice
+        .client
+        .send_direct("not a username", "hi")
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        match alice.next_event().await {
+            Event::Error(error) => assert_eq!(error.code, "invalid_message"),
+            other => panic!("expected an error frame, got {:?}", other),
+        }
+    }
+    // The connection stays usable
+    alice.client.send(DEFAULT_GROUP, "fine").await.unwrap();
+    assert_eq!(alice.next_message().await.body, "fine");
+}
+
+#[tokio::test]
+async fn unresponsive_sockets_are_reaped() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let config = ServerConfig {
+        websocket: WebSocketConfig {
+            ping_interval_ms: 100,
+            max_missed_pongs: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server = TestServer::start_with(config).await;
+    // The SDK answers pings while its events are polled
+    let mut alice = server.connect_as("alice").await;
+
+    // Any WebSocket library would answer pings as soon as it read them, so
+    // shake hands by hand and then just collect what the server sends
+    let mut silent = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+    let handshake = format!(
+        "GET /v1/ws HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        server.addr()
+    );
+    silent.write_all(handshake.as_bytes()).await.unwrap();
+    for _ in 0..4 {
+        alice.assert_no_message().await;
+    }
+    let mut received = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(5), silent.read_to_end(&mut received));
+    read.await.expect("server hangs up").unwrap();
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(received.starts_with(b"HTTP/1.1 101"));
+    // Two empty pings, then a close frame with 1001 and the reason
+    let mut expected = vec![0x89, 0, 0x89, 0, 0x88, 14, 0x03, 0xe9];
+    expected.extend_from_slice(b"ping timeout");
+    assert_eq!(received[head_end..], expected);
+
+    alice
+        .client
+        .send(DEFAULT_GROUP, "still here")
+        .await
+        .unwrap();
+    assert_eq!(alice.next_message().await.body, "still here");
+}
+
+#[tokio::test]
+async fn heartbeat_measures_latency() {
+    let server = TestServer::start().await;
+    let mut client = server.client();
+    client.set_heartbeat(Some(Heartbeat {
+        interval: Duration::from_millis(50),
+        timeout: Duration::from_secs(5),
+    }));
+    let mut peer = Peer::connect(client).await;
+    assert!(peer.next_latency().await < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn silent_server_is_detected() {
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use tokio_tungstenite::tungstenite::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue};
+
+    // Completes the handshake, then never reads, so pings go unanswered
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        // The error type is fixed by tungstenite's callback signature
+        #[allow(clippy::result_large_err)]
+        let agree = |_: &Request, mut response: Response| {
+            let protocol = HeaderValue::from_static(WS_SUBPROTOCOL);
+            response
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_PROTOCOL, protocol);
+            Ok(response)
+        };
+        let socket = tokio_tungstenite::accept_hdr_async(stream, agree)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        drop(socket);
+    });
+
+    let mut client = project_veil::sdk::VeilClient::new(format!("http://{}", addr));
+    client.set_heartbeat(Some(Heartbeat {
+        interval: Duration::from_millis(50),
+        timeout: Duration::from_millis(300),
+    }));
+    let mut events = client.connect().await.unwrap();
+    let next = tokio::time::timeout(Duration::from_secs(2), events.next()).await;
+    match next.expect("timeout detected in time") {
+        Some(Err(ClientError::Timeout(silence))) => {
+            assert_eq!(silence, Duration::from_millis(300))
+        }
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    assert!(events.next().await.is_none());
+    server.abort();
+}
+
+#[tokio::test]
+async fn presence_follows_connections() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    assert_eq!(
+        alice.next_presence_of("alice").await.status,
+        PresenceStatus::Online
+    );
+
+    // Presence is shared through private groups; everyone is in the open ones
+    server.client().create_user("bob", PASSWORD).await.unwrap();
+    let members = ["alice".to_string(), "bob".to_string()];
+    alice.client.create_group("pair", &members).await.unwrap();
+    let mut bob = server.connect_existing("bob").await;
+    let bob_id = bob.client.user().unwrap().id.clone();
+    assert_eq!(
+        alice.next_presence_of("bob").await.status,
+        PresenceStatus::Online
+    );
+    bob.next_presence_of("bob").await;
+    // Newcomers hear about everyone already online
+    assert_eq!(
+        bob.next_presence_of("alice").await.status,
+        PresenceStatus::Online
+    );
+
+    let away = bob.client.set_away(true).await.unwrap();
+    assert_eq!(away.status, PresenceStatus::Away);
+    assert_eq!(
+        alice.next_presence_of("bob").await.status,
+        PresenceStatus::Away
+    );
+    assert_eq!(
+        bob.next_presence_of("bob").await.status,
+        PresenceStatus::Away
+    );
+    let url = format!("http://{}/users/{}/presence", server.addr(), bob_id);
+    let token = login_token(&server, "alice").await;
+    let forbidden = reqwest::Client::new()
+        .put(&url)
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "away": false }))
+        .send();
+    assert_eq!(forbidden.await.unwrap().status(), 403);
+
+    // Outsiders hear nothing and may not look
+    let mut carol = server.connect_as("carol").await;
+    carol.next_presence_of("carol").await;
+    match carol.client.presence(&bob_id).await {
+        Err(ClientError::Api { status, .. }) => assert_eq!(status.as_u16(), 403),
+        other => panic!("expected 403, got {:?}", other),
+    }
+
+    bob.client.disconnect().await.unwrap();
+    let offline = alice.next_presence_of("bob").await;
+    assert_eq!(offline.status, PresenceStatus::Offline);
+    assert!(offline.last_seen.is_some());
+    assert_eq!(alice.client.presence(&bob_id).await.unwrap(), offline);
+    let heard = tokio::time::timeout(Duration::from_millis(300), carol.next_presence_of("bob"));
+    assert!(heard.await.is_err(), "carol heard about bob");
+}
+
+#[tokio::test]
+async fn hidden_presence_looks_offline() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    server
+        .client()
+        .create_user("carol", PASSWORD)
+        .await
+        .unwrap();
+    let members = ["alice".to_string(), "carol".to_string()];
+    alice.client.create_group("pair", &members).await.unwrap();
+    let mut carol = server.connect_existing("carol").await;
+    let carol_id = carol.client.user().unwrap().id.clone();
+    alice.next_presence_of("carol").await;
+    carol.next_presence_of("carol").await;
+
+    let hide = UpdateUserPayload {
+        hide_presence: Some(true),
+        ..Default::default()
+    };
+    carol.client.update_profile(&hide).await.unwrap();
+    let hidden = alice.next_presence_of("carol").await;
+    assert_eq!(hidden.status, PresenceStatus::Offline);
+    assert_eq!(
+        carol.next_presence_of("carol").await.status,
+        PresenceStatus::Online
+    );
+
+    // Coming and going goes unannounced, and leaves no last-seen time
+    carol.client.disconnect().await.unwrap();
+    let _events = carol.client.connect().await.unwrap();
+    alice.assert_no_message().await;
+    assert_eq!(alice.client.presence(&carol_id).await.unwrap(), hidden);
+    assert_eq!(
+        carol.client.presence(&carol_id).await.unwrap().status,
+        PresenceStatus::Online
+    );
+}
+
+/// Send `frame` on a fresh anonymous socket and return the close code the
+/// server answers with.
+async fn raw_close_code(server: &TestServer, frame: WsMessage) -> u16 {
+    use futures::{SinkExt, StreamExt};
+
+    let url = format!("ws://{}/v1/ws", server.addr());
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    socket.send(frame).await.unwrap();
+    loop {
+        let next = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next());
+        match next.await.expect("close within timeout") {
+            Some(Ok(WsMessage::Close(Some(frame)))) => return frame.code.into(),
+            Some(Ok(_)) => continue,
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+}
+
+async fn login_token(server: &TestServer, username: &str) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/login", server.addr()))
+        .json(&serde_json::json!({ "username": username, "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    response.json::<LoginResponse>().await.unwrap().token
+}
+
+#[tokio::test]
+async fn typing_reaches_readers_and_is_throttled() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let mut carol = server.connect_as("carol").await;
+    // Typing goes to registered connections only; a peer's own presence
+    // frame says it is registered
+    for (peer, name) in [
+        (&mut alice, "alice"),
+        (&mut bob, "bob"),
+        (&mut carol, "carol"),
+    ] {
+        peer.next_presence_of(name).await;
+    }
+
+    let direct = Target::Direct("bob".to_string());
+    alice.client.send_typing(&direct).await.unwrap();
+    match bob.next_event().await {
+        Event::Typing(typing) => {
+            assert_eq!(typing.target, direct);
+            assert_eq!(typing.sender.as_deref(), Some("alice"));
+        }
+        other => panic!("expected a typing frame, got {:?}", other),
+    }
+
+    // Three typing frames per connection at once; the rest are dropped
+    let general = Target::Group(DEFAULT_GROUP.to_string());
+    for _ in 0..3 {
+        alice.client.send_typing(&general).await.unwrap();
+    }
+    for peer in [&mut bob, &mut carol] {
+        for _ in 0..2 {
+            match peer.next_event().await {
+                Event::Typing(typing) => assert_eq!(typing.target, general),
+                other => panic!("expected a typing frame, got {:?}", other),
+            }
+        }
+        peer.assert_no_message().await;
+    }
+    // Neither echoed back nor complained about
+    alice.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn receipts_reach_only_the_author() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+    let mut carol = server.connect_as("carol").await;
+    let mut guest = server.connect_anonymous().await;
+    for (peer, name) in [
+        (&mut alice, "alice"),
+        (&mut bob, "bob"),
+        (&mut carol, "carol"),
+    ] {
+        peer.next_presence_of(name).await;
+    }
+
+    alice.client.send(DEFAULT_GROUP, "hello").await.unwrap();
+    let chat = bob.next_message().await;
+    let id = chat.id.clone().expect("server assigns message ids");
+    assert_eq!(alice.next_message().await.id, Some(id.clone()));
+    carol.next_message().await;
+    guest.next_message().await;
+
+    let read = ReceiptBody {
+        status: ReceiptStatus::Read,
+        message_ids: vec![id],
+    };
+    bob.client.send_receipt("alice", &read).await.unwrap();
+    match alice.next_event().await {
+        Event::Receipt { sender, body } => {
+            assert_eq!(sender, "bob");
+            assert_eq!(body, read);
+        }
+        other => panic!("expected a receipt, got {:?}", other),
+    }
+    carol.assert_no_message().await;
+    bob.assert_no_message().await;
+
+    // Receipts need a sender and a recipient
+    guest.client.send_receipt("alice", &read).await.unwrap();
+    bob.client.send_receipt("nobody", &read).await.unwrap();
+    for peer in [&mut guest, &mut bob] {
+        match peer.next_event().await {
+            Event::Error(error) => assert_eq!(error.code, "invalid_message"),
+            other => panic!("expected an error frame, got {:?}", other),
+        }
+    }
+
+    // Turning receipts off stops them even from clients that send anyway
+    let hide = UpdateUserPayload {
+        hide_receipts: Some(true),
+        ..Default::default()
+    };
+    bob.client.update_profile(&hide).await.unwrap();
+    bob.client.send_receipt("alice", &read).await.unwrap();
+    alice.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn group_history_pages_by_sequence() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_as("alice").await;
+    for n in 1..=5 {
+        alice
+            .client
+            .send(DEFAULT_GROUP, &format!("message {}", n))
+            .await
+            .unwrap();
+        assert_eq!(alice.next_message().await.seq, Some(n));
+    }
+    alice
+        .client
+        .send_direct("alice", "not stored")
+        .await
+        .unwrap();
+    alice.next_message().await;
+
+    let page = |before, after, limit| HistoryQuery {
+        before,
+        after,
+        limit,
+    };
+    let seqs =
+        |page: &HistoryPage| -> Vec<u64> { page.messages.iter().map(|m| m.seq.unwrap()).collect() };
+    // Anonymous callers may read open groups
+    let guest = server.client();
+    let newest = guest
+        .history(DEFAULT_GROUP, &page(None, None, Some(2)))
+        .await
+        .unwrap();
+    assert_eq!((seqs(&newest), newest.more), (vec![4, 5], true));
+    assert_eq!(newest.messages[1].body, "message 5");
+    let older = guest
+        .history(DEFAULT_GROUP, &page(Some(4), None, Some(2)))
+        .await
+        .unwrap();
+    assert_eq!((seqs(&older), older.more), (vec![2, 3], true));
+    let since = guest
+        .history(DEFAULT_GROUP, &page(None, Some(3), None))
+        .await
+        .unwrap();
+    assert_eq!((seqs(&since), since.more), (vec![4, 5], false));
+
+    // The WebSocket request answers with the same page
+    alice
+        .client
+        .request_history(DEFAULT_GROUP, &page(Some(4), None, Some(2)))
+        .await
+        .unwrap();
+    match alice.next_event().await {
+        Event::History(history) => assert_eq!(history, older),
+        other => panic!("expected a history page, got {:?}", other),
+    }
+
+    // Private groups are for members only, and unknown groups are missing
+    let members = ["alice".to_string()];
+    alice.client.create_group("ops", &members).await.unwrap();
+    let refusals = [
+        (guest.history("ops", &HistoryQuery::default()).await, 403),
+        (guest.history("nope", &HistoryQuery::default()).await, 404),
+    ];
+    for (result, expected) in refusals {
+        match result {
+            Err(ClientError::Api { status, .. }) => assert_eq!(status, expected),
+            other => panic!("expected {}, got {:?}", expected, other),
+        }
+    }
+    alice
+        .client
+        .request_history("nope", &HistoryQuery::default())
+        .await
+        .unwrap();
+    match alice.next_event().await {
+        Event::Error(error) => assert_eq!(error.code, "group_not_found"),
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn retention_sweeps_old_group_messages() {
+    let config = ServerConfig {
+        history: HistoryConfig {
+            sweep_interval_ms: 20,
+            ..Default::default()
+        },
+        ..with_admin("root")
+    };
+    let server = TestServer::start_with(config).await;
+    let mut alice = server.connect_as("alice").await;
+    let bob = server.connect_as("bob").await;
+    let root = server.connect_existing("root").await;
+    let members = ["alice".to_string()];
+    alice.client.create_group("ops", &members).await.unwrap();
+
+    // Members set a private group's retention; only admins an open group's
+    let keep_two = Retention {
+        max_count: Some(2),
+        ..Default::default()
+    };
+    let status = |result: Result<_, ClientError>| match result {
+        Err(ClientError::Api { status, .. }) => status.as_u16(),
+        other => panic!("expected an API error, got {:?}", other),
+    };
+    let guest = server.client();
+    assert_eq!(status(guest.set_retention("ops", &keep_two).await), 401);
+    assert_eq!(
+        status(bob.client.set_retention("ops", &keep_two).await),
+        403
+    );
+    assert_eq!(
+        status(alice.client.set_retention("nope", &keep_two).await),
+        404
+    );
+    let zero = Retention {
+        max_count: Some(0),
+        ..Default::default()
+    };
+    assert_eq!(status(alice.client.set_retention("ops", &zero).await), 422);
+    assert_eq!(
+        status(alice.client.set_retention(DEFAULT_GROUP, &keep_two).await),
+        403
+    );
+    root.client
+        .set_retention(DEFAULT_GROUP, &keep_two)
+        .await
+        .unwrap();
+    let group = alice.client.set_retention("ops", &keep_two).await.unwrap();
+    assert_eq!(group.retention, keep_two);
+
+    for n in 1..=5 {
+        alice
+            .client
+            .send("ops", &format!("message {}", n))
+            .await
+            .unwrap();
+        alice.next_message().await;
+    }
+    let seqs =
+        |page: HistoryPage| -> Vec<u64> { page.messages.iter().map(|m| m.seq.unwrap()).collect() };
+    let stored = || async {
+        seqs(
+            alice
+                .client
+                .history("ops", &HistoryQuery::default())
+                .await
+                .unwrap(),
+        )
+    };
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while stored().await != [4, 5] {
+        assert!(tokio::time::Instant::now() < deadline, "count not enforced");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Past the age bound everything goes, but numbering carries on
+    let one_second = Retention {
+        max_age_secs: Some(1),
+        ..Default::default()
+    };
+    alice
+        .client
+        .set_retention("ops", &one_second)
+        .await
+        .unwrap();
+    while !stored().await.is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "age not enforced");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    alice.client.send("ops", "after the sweep").await.unwrap();
+    assert_eq!(alice.next_message().await.seq, Some(6));
+}
+
+#[tokio::test]
+async fn attachments_are_stored_encrypted_in_the_blob_store() {
+    let config = ServerConfig {
+        blobs: BlobConfig {
+            dir: server_blob_dir(),
+            max_blob_bytes: 1024,
+            quota_bytes_per_user: 1500,
+        },
+        ..Default::default()
+    };
+    let server = TestServer::start_with(config).await;
+    let alice = server.connect_as("alice").await;
+    let mut bob = server.connect_as("bob").await;
+
+    // Alice encrypts and uploads a file, then sends its key in a message
+    let file = b"meeting notes: nothing to see here".to_vec();
+    let mut reported = Vec::new();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let attachment = alice
+        .client
+        .upload_attachment("notes.txt", &file, move |done, total| {
+            let _ = tx.send((done, total));
+        })
+        .await
+        .unwrap();
+    while let Ok(step) = rx.try_recv() {
+        reported.push(step);
+    }
+    let sealed = file.len() as u64 + 16; // Plus the authentication tag
+    assert_eq!(reported.last(), Some(&(sealed, sealed)));
+    assert_eq!(attachment.size, file.len() as u64);
+    let body = encode_payload(&MessagePayload {
+        text: "notes.txt".to_string(),
+        attachment: Some(attachment.clone()),
+        ..Default::default()
+    });
+    alice.client.send(DEFAULT_GROUP, &body).await.unwrap();
+
+    // The server only ever holds ciphertext
+    let guest = server.client();
+    let blob = guest
+        .download_blob(&attachment.hash, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(blob.len() as u64, sealed);
+    assert!(!blob.windows(8).any(|w| w == b"meeting "));
+
+    // Bob gets the key with the message and can read the file
+    let received = decode_payload(&bob.next_message().await.body);
+    let shared = received.attachment.expect("attachment in payload");
+    let data = bob
+        .client
+        .download_attachment(&shared, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(data, file);
+    let wrong_key = Attachment {
+        key: "00".repeat(32),
+        ..shared.clone()
+    };
+    match bob.client.download_attachment(&wrong_key, |_, _| {}).await {
+        Err(ClientError::Attachment(_)) => {}
+        other => panic!("expected an attachment error, got {:?}", other),
+    }
+
+    fn status<T: std::fmt::Debug>(result: Result<T, ClientError>) -> (u16, String) {
+        match result {
+            Err(ClientError::Api { status, code, .. }) => (status.as_u16(), code),
+            other => panic!("expected an API error, got {:?}", other),
+        }
+    }
+    let error = |status: u16, code: &str| (status, code.to_string());
+    // Uploads need a session, fit the size limit and the uploader's quota;
+    // uploading the same blob again costs nothing
+    assert_eq!(
+        status(guest.upload_blob(vec![1; 10], |_, _| {}).await),
+        error(401, "unauthorized")
+    );
+    assert_eq!(
+        status(alice.client.upload_blob(vec![1; 1025], |_, _| {}).await),
+        error(413, "blob_too_large")
+    );
+    assert_eq!(
+        status(alice.client.upload_blob(Vec::new(), |_, _| {}).await),
+        error(422, "empty_blob")
+    );
+    alice
+        .client
+        .upload_blob(vec![2; 1000], |_, _| {})
+        .await
+        .unwrap();
+    alice
+        .client
+        .upload_blob(vec![2; 1000], |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(
+        status(alice.client.upload_blob(vec![3; 1000], |_, _| {}).await),
+        error(413, "quota_exceeded")
+    );
+    bob.client
+        .upload_blob(vec![3; 1000], |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(
+        status(guest.download_blob("../etc/passwd", |_, _| {}).await),
+        error(422, "invalid_hash")
+    );
+    assert_eq!(
+        status(guest.download_blob(&"0".repeat(64), |_, _| {}).await),
+        error(404, "blob_not_found")
+    );
+    let _ = tokio::fs::remove_dir_all(server_blob_dir()).await;
+}
+
+fn server_blob_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("veil-test-blobs-{}", std::process::id()))
+}
@@ -0,0 +1,175 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use project_veil::client::app_state::App;
+use project_veil::client::conversation::unix_millis;
+use project_veil::client::events::Flow;
+use project_veil::client::tui::ui;
+use project_veil::proto::{
+    ChatMessage, Group, HistoryPage, HistoryQuery, LoginResponse, ReceiptBody, Retention, Role,
+    Target, Typing, User,
+};
+use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+use std::time::Duration;
+
+// --- TUI test harness ---
+//
+// Drives an `App` through the same event path as the real client and
+// renders it into an in-memory terminal. Network input is simulated by
+// calling the methods the WebSocket receive loop and API calls use.
+
+pub struct Tui {
+    pub app: App,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Tui {
+    pub fn new(width: u16, height: u16) -> Tui {
+        let mut app = App::default();
+        app.resize(width, height);
+        let terminal = Terminal::new(TestBackend::new(width, height)).expect("test terminal");
+        Tui { app, terminal }
+    }
+
+    /// Handle `event`, then run the requests it queued as the main loop
+    /// does. Without a server they fail, which shows in the status bar.
+    pub async fn event(&mut self, event: Event) -> Flow {
+        let flow = self.app.handle_event(event);
+        loop {
+            let requests = std::mem::take(&mut self.app.requests);
+            if requests.is_empty() {
+                return flow;
+            }
+            for request in requests {
+                let update = request.await;
+                update(&mut self.app);
+            }
+        }
+    }
+
+    pub async fn press(&mut self, code: KeyCode) -> Flow {
+        self.press_with(code, KeyModifiers::NONE).await
+    }
+
+    pub async fn press_with(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Flow {
+        self.event(Event::Key(KeyEvent::new(code, modifiers))).await
+    }
+
+    /// Type `text` one key at a time.
+    pub async fn type_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.press(KeyCode::Char(c)).await;
+        }
+    }
+
+    pub async fn resize(&mut self, width: u16, height: u16) {
+        // The next draw picks up the backend's new size
+        self.terminal.backend_mut().resize(width, height);
+        self.event(Event::Resize(width, height)).await;
+    }
+
+    // --- Simulated network input ---
+
+    pub fn receive(&mut self, target: Target, sender: &str, body: &str) {
+        self.receive_with_id(target, None, sender, body);
+    }
+
+    pub fn receive_with_id(&mut self, target: Target, id: Option<&str>, sender: &str, body: &str) {
+        self.app.receive_message(ChatMessage {
+            target,
+            id: id.map(str::to_string),
+            seq: None,
+            sender: Some(sender.to_string()),
+            sent_at: None, // Timestamps render in local time, so leave them out
+            body: body.to_string(),
+        });
+    }
+
+    /// A page of `group`'s history holding `(seq, sender, body)` messages;
+    /// returns the follow-up request the app makes, if any.
+    pub fn history(
+        &mut self,
+        group: &str,
+        messages: &[(u64, &str, &str)],
+        more: bool,
+    ) -> Option<HistoryQuery> {
+        let messages = messages
+            .iter()
+            .map(|(seq, sender, body)| ChatMessage {
+                target: Target::Group(group.to_string()),
+                id: Some(format!("m{}", seq)),
+                seq: Some(*seq),
+                sender: Some(sender.to_string()),
+                sent_at: None,
+                body: body.to_string(),
+            })
+            .collect();
+        self.app.receive_history(HistoryPage {
+            group: group.to_string(),
+            messages,
+            more,
+        })
+    }
+
+    /// Let `elapsed` pass for disappearing messages.
+    pub fn wait(&mut self, elapsed: Duration) {
+        self.app
+            .expire_messages(unix_millis() + elapsed.as_millis() as u64);
+    }
+
+    pub fn typing(&mut self, target: Target, sender: &str) {
+        self.app.receive_typing(Typing {
+            target,
+            sender: Some(sender.to_string()),
+        });
+    }
+
+    pub fn receipt(&mut self, body: ReceiptBody) {
+        self.app.receive_receipt(body);
+    }
+
+    /// Act as `username` without a server, as if logged in earlier.
+    pub fn log_in_as(&mut self, username: &str) {
+        self.app.client.resume(LoginResponse {
+            token: "test-token".to_string(),
+            user: User {
+                id: format!("{}-id", username),
+                username: username.to_string(),
+                display_name: None,
+                avatar_hash: None,
+                status_text: None,
+                role: Role::User,
+                hide_presence: false,
+                hide_receipts: false,
+            },
+        });
+    }
+
+    pub fn users(&mut self, names: &[&str]) {
+        self.app.user_list = names.iter().map(|n| n.to_string()).collect();
+    }
+
+    pub fn groups(&mut self, groups: &[(&str, &[&str])]) {
+        let groups = groups
+            .iter()
+            .map(|(name, members)| Group {
+                name: name.to_string(),
+                members: members.iter().map(|m| m.to_string()).collect(),
+                retention: Retention::default(),
+            })
+            .collect();
+        self.app.sync_groups(groups);
+    }
+
+    // --- Rendering ---
+
+    pub fn buffer(&mut self) -> &Buffer {
+        let app = &self.app;
+        self.terminal.draw(|f| ui(f, app)).expect("draw");
+        self.terminal.backend().buffer()
+    }
+
+    /// The screen as text, one quoted line per row, for snapshots.
+    pub fn render(&mut self) -> String {
+        self.buffer();
+        self.terminal.backend().to_string()
+    }
+}
@@ -0,0 +1,281 @@
+#![cfg(feature = "client")]
+
+mod harness;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+use harness::Tui;
+use insta::assert_snapshot;
+use project_veil::client::events::Flow;
+use project_veil::client::layout::Focus;
+use project_veil::proto::{
+    Attachment, MessagePayload, PresenceStatus, ReceiptBody, ReceiptStatus, Target,
+};
+use project_veil::sdk::encode_payload;
+use std::time::Duration;
+
+fn general() -> Target {
+    Target::Group("general".to_string())
+}
+
+#[tokio::test]
+async fn startup() {
+    let mut tui = Tui::new(80, 24);
+    tui.users(&["alice", "bob"]);
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn incoming_messages_wrap_and_mark_unread() {
+    let mut tui = Tui::new(80, 24);
+    tui.groups(&[("general", &[]), ("ops", &[])]);
+    tui.receive(
+        general(),
+        "alice",
+        "a **bold** claim with `code` and a long enough tail to wrap onto the next row",
+    );
+    tui.receive(Target::Group("ops".to_string()), "bob", "deploy done");
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn command_completion_and_help() {
+    let mut tui = Tui::new(80, 24);
+    tui.type_str("/he").await;
+    tui.press(KeyCode::Tab).await;
+    assert_eq!(tui.app.input.as_str(), "/help ");
+    tui.press(KeyCode::Enter).await;
+    assert!(tui.app.input.is_empty());
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn invalid_command_keeps_input_with_hint() {
+    let mut tui = Tui::new(80, 24);
+    tui.type_str("/open").await;
+    tui.press(KeyCode::Enter).await;
+    assert_eq!(tui.app.input.as_str(), "/open");
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn scrollback_shows_indicator() {
+    let mut tui = Tui::new(80, 24);
+    for i in 0..40 {
+        tui.receive(general(), "alice", &format!("message {}", i));
+    }
+    tui.press(KeyCode::PageUp).await;
+    tui.receive(general(), "bob", "newer");
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn scrollback_reaches_the_top_of_wrapped_history() {
+    let mut tui = Tui::new(80, 24);
+    for i in 0..20 {
+        let body = format!("message {} {}", i, "and a long tail ".repeat(6));
+        tui.receive(general(), "alice", &body);
+    }
+    tui.render(); // Lays out the history pane
+    for _ in 0..20 {
+        tui.press(KeyCode::PageUp).await;
+    }
+    let screen = tui.render();
+    assert!(screen.contains("message 0 "), "{}", screen);
+    assert_snapshot!(screen);
+}
+
+#[tokio::test]
+async fn narrow_terminal_collapses_sidebar_and_pops_up_users() {
+    let mut tui = Tui::new(100, 30);
+    tui.users(&["alice", "bob"]);
+    tui.groups(&[("ops", &[])]);
+    tui.receive(Target::Group("ops".to_string()), "bob", "hi");
+    tui.resize(50, 16).await;
+    assert_snapshot!("narrow", tui.render());
+    tui.press(KeyCode::F(2)).await;
+    assert_eq!(tui.app.focus, Focus::Users);
+    assert_snapshot!("narrow_users_popup", tui.render());
+    // Esc closes the popup rather than quitting
+    assert_eq!(tui.press(KeyCode::Esc).await, Flow::Continue);
+    assert!(!tui.app.users_popup);
+    assert_eq!(tui.press(KeyCode::Esc).await, Flow::Quit);
+}
+
+#[tokio::test]
+async fn focus_moves_between_panes() {
+    let mut tui = Tui::new(80, 24);
+    tui.users(&["alice", "bob"]);
+    let focused = tui.app.theme.focused_border;
+
+    // Top-left corners at 80x24: one cell of margin, then the 24-column sidebar
+    let history = (25, 7);
+    let input = (25, 15);
+
+    // The input is focused first
+    assert_eq!(tui.buffer()[input].style().fg, focused.fg);
+
+    tui.press(KeyCode::F(6)).await;
+    assert_eq!(tui.app.focus, Focus::History);
+    let buffer = tui.buffer();
+    assert_eq!(buffer[history].style().fg, focused.fg);
+    assert_ne!(buffer[input].style().fg, focused.fg);
+
+    // Typing from another pane goes back to the input
+    tui.type_str("x").await;
+    assert_eq!(tui.app.focus, Focus::Input);
+    assert_eq!(tui.app.input.as_str(), "x");
+}
+
+#[tokio::test]
+async fn user_list_opens_direct_conversation() {
+    let mut tui = Tui::new(80, 24);
+    tui.users(&["alice", "bob"]);
+    tui.press_with(KeyCode::F(6), KeyModifiers::SHIFT).await;
+    assert_eq!(tui.app.focus, Focus::Users);
+    tui.press(KeyCode::Down).await;
+    tui.press(KeyCode::Enter).await;
+    assert_eq!(tui.app.focus, Focus::Input);
+    assert_eq!(
+        tui.app.active_conversation().target,
+        Target::Direct("bob".to_string())
+    );
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn direct_conversation_uses_the_registered_name() {
+    let mut tui = Tui::new(80, 24);
+    tui.log_in_as("bob");
+    tui.users(&["Alice", "bob"]);
+    tui.type_str("/dm alice").await;
+    tui.press(KeyCode::Enter).await;
+    let alice = Target::Direct("Alice".to_string());
+    assert_eq!(tui.app.active_conversation().target, alice);
+    // The server files the reply under the registered name too
+    tui.receive(alice.clone(), "Alice", "hi");
+    assert_eq!(tui.app.conversations.len(), 2);
+    tui.type_str("/dm ALICE").await;
+    tui.press(KeyCode::Enter).await;
+    assert_eq!(tui.app.active_conversation().target, alice);
+    assert_eq!(tui.app.conversations.len(), 2);
+}
+
+#[tokio::test]
+async fn status_bar_shows_latency() {
+    let mut tui = Tui::new(80, 24);
+    tui.app.status = "Connected to http://localhost:3000".to_string();
+    tui.app.latency = Some(Duration::from_millis(42));
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn user_list_shows_presence() {
+    let mut tui = Tui::new(80, 24);
+    tui.users(&["alice", "bob", "carol"]);
+    tui.app
+        .presence
+        .insert("alice".to_string(), PresenceStatus::Online);
+    tui.app
+        .presence
+        .insert("bob".to_string(), PresenceStatus::Away);
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn typing_and_receipts_show_in_conversation() {
+    let mut tui = Tui::new(100, 24);
+    tui.log_in_as("alice");
+    tui.receive_with_id(general(), Some("m1"), "alice", "first");
+    tui.receive_with_id(general(), Some("m2"), "alice", "second");
+    tui.receive_with_id(general(), Some("m3"), "alice", "third");
+    tui.receipt(ReceiptBody {
+        status: ReceiptStatus::Read,
+        message_ids: vec!["m1".to_string()],
+    });
+    tui.receipt(ReceiptBody {
+        status: ReceiptStatus::Delivered,
+        message_ids: vec!["m1".to_string(), "m2".to_string()],
+    });
+    tui.typing(general(), "bob");
+
+    // Others' messages get a receipt, read since the conversation is open
+    tui.receive_with_id(general(), Some("m4"), "carol", "hi alice");
+    assert_eq!(tui.app.outbox.len(), 1);
+    assert_eq!(tui.app.outbox[0].0, "carol");
+    assert_eq!(tui.app.outbox[0].1.status, ReceiptStatus::Read);
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn history_pages_merge_with_live_messages() {
+    let mut tui = Tui::new(80, 24);
+    // Pages may overlap what is already shown, e.g. a message that also
+    // arrived live
+    tui.history("general", &[(7, "bob", "live")], false);
+    let next = tui.history(
+        "general",
+        &[(5, "alice", "older"), (6, "bob", "old"), (7, "bob", "live")],
+        true,
+    );
+    assert_eq!(next, None); // Older pages are not fetched on their own
+    assert_eq!(tui.app.active_conversation().unread, 0);
+
+    // Catching up after a reconnect follows pages to the end
+    let next = tui.history("general", &[(8, "alice", "missed")], true);
+    assert_eq!(next.and_then(|query| query.after), Some(8));
+    assert_snapshot!(tui.render());
+}
+
+#[tokio::test]
+async fn disappearing_messages_vanish_after_their_timer() {
+    let mut tui = Tui::new(80, 24);
+    tui.log_in_as("alice");
+    tui.type_str("/timer 5m").await;
+    tui.press(KeyCode::Enter).await;
+    let vanishing = encode_payload(&MessagePayload {
+        text: "gone in 30s".to_string(),
+        expires_after_secs: Some(30),
+        ..Default::default()
+    });
+    tui.receive_with_id(general(), Some("m1"), "bob", &vanishing);
+    tui.receive_with_id(general(), Some("m2"), "bob", "here to stay");
+    assert_snapshot!(tui.render());
+
+    tui.wait(Duration::from_secs(29));
+    assert!(tui.render().contains("gone in 30s"));
+    tui.wait(Duration::from_secs(31));
+    let screen = tui.render();
+    assert!(!screen.contains("gone in 30s"));
+    assert!(screen.contains("here to stay"));
+}
+
+#[tokio::test]
+async fn attachments_are_numbered_for_saving() {
+    let mut tui = Tui::new(80, 24);
+    tui.log_in_as("alice");
+    let attachment = |name: &str, size| MessagePayload {
+        text: name.to_string(),
+        attachment: Some(Attachment {
+            name: name.to_string(),
+            size,
+            hash: "ab".repeat(32),
+            key: "cd".repeat(32),
+        }),
+        ..Default::default()
+    };
+    let report = encode_payload(&attachment("report.pdf", 1_300_000));
+    let photo = encode_payload(&attachment("photo.jpg", 48_000));
+    tui.receive_with_id(general(), Some("m1"), "bob", &report);
+    tui.receive_with_id(general(), Some("m2"), "carol", "see above");
+    tui.receive_with_id(general(), Some("m3"), "carol", &photo);
+    assert_snapshot!(tui.render());
+
+    // Unknown numbers are refused before anything is downloaded
+    tui.type_str("/save 3 photo.jpg").await;
+    tui.press(KeyCode::Enter).await;
+    assert_eq!(
+        tui.app.input_hint.as_deref(),
+        Some("no attachment [3] in this conversation")
+    );
+    assert_eq!(tui.app.input.as_str(), "");
+}